@@ -36,11 +36,28 @@ mod static_fns {
             cb: pa_context_notify_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_operation;
+        pub fn pa_context_get_card_info_by_index(
+            c: *mut pa_context,
+            idx: u32,
+            cb: pa_card_info_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
+        pub fn pa_context_get_card_info_list(
+            c: *mut pa_context,
+            cb: pa_card_info_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
         pub fn pa_context_get_server_info(
             c: *const pa_context,
             cb: pa_server_info_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_operation;
+        pub fn pa_context_get_sink_info_by_index(
+            c: *mut pa_context,
+            idx: u32,
+            cb: pa_sink_info_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
         pub fn pa_context_get_sink_info_by_name(
             c: *const pa_context,
             name: *const c_char,
@@ -58,22 +75,47 @@ mod static_fns {
             cb: pa_sink_input_info_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_operation;
+        pub fn pa_context_get_source_info_by_index(
+            c: *mut pa_context,
+            idx: u32,
+            cb: pa_source_info_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
+        pub fn pa_context_get_source_info_by_name(
+            c: *mut pa_context,
+            name: *const c_char,
+            cb: pa_source_info_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
         pub fn pa_context_get_source_info_list(
             c: *const pa_context,
             cb: pa_source_info_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_operation;
         pub fn pa_context_get_state(c: *const pa_context) -> pa_context_state_t;
+        pub fn pa_context_get_server_protocol_version(c: *const pa_context) -> u32;
         pub fn pa_context_new(
             mainloop: *mut pa_mainloop_api,
             name: *const c_char,
         ) -> *mut pa_context;
+        pub fn pa_context_new_with_proplist(
+            mainloop: *mut pa_mainloop_api,
+            name: *const c_char,
+            proplist: *const pa_proplist,
+        ) -> *mut pa_context;
         pub fn pa_context_rttime_new(
             c: *const pa_context,
             usec: pa_usec_t,
             cb: pa_time_event_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_time_event;
+        pub fn pa_context_set_card_profile_by_index(
+            c: *mut pa_context,
+            idx: u32,
+            profile: *const c_char,
+            cb: pa_context_success_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
         pub fn pa_context_set_sink_input_volume(
             c: *mut pa_context,
             idx: u32,
@@ -81,6 +123,20 @@ mod static_fns {
             cb: pa_context_success_cb_t,
             userdata: *mut c_void,
         ) -> *mut pa_operation;
+        pub fn pa_context_set_sink_port_by_name(
+            c: *mut pa_context,
+            name: *const c_char,
+            port: *const c_char,
+            cb: pa_context_success_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
+        pub fn pa_context_set_source_port_by_name(
+            c: *mut pa_context,
+            name: *const c_char,
+            port: *const c_char,
+            cb: pa_context_success_cb_t,
+            userdata: *mut c_void,
+        ) -> *mut pa_operation;
         pub fn pa_context_set_state_callback(
             c: *mut pa_context,
             cb: pa_context_notify_cb_t,
@@ -128,6 +184,13 @@ mod static_fns {
             userdata: *mut c_void,
         );
         pub fn pa_proplist_gets(p: *mut pa_proplist, key: *const c_char) -> *const c_char;
+        pub fn pa_proplist_new() -> *mut pa_proplist;
+        pub fn pa_proplist_free(p: *mut pa_proplist);
+        pub fn pa_proplist_sets(
+            p: *mut pa_proplist,
+            key: *const c_char,
+            value: *const c_char,
+        ) -> c_int;
         pub fn pa_rtclock_now() -> pa_usec_t;
         pub fn pa_stream_begin_write(
             p: *mut pa_stream,
@@ -307,6 +370,20 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_GET_CARD_INFO_BY_INDEX = {
+                let fp = dlsym(h, cstr!("pa_context_get_card_info_by_index"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
+            PA_CONTEXT_GET_CARD_INFO_LIST = {
+                let fp = dlsym(h, cstr!("pa_context_get_card_info_list"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_GET_SERVER_INFO = {
                 let fp = dlsym(h, cstr!("pa_context_get_server_info"));
                 if fp.is_null() {
@@ -314,6 +391,13 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_GET_SINK_INFO_BY_INDEX = {
+                let fp = dlsym(h, cstr!("pa_context_get_sink_info_by_index"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_GET_SINK_INFO_BY_NAME = {
                 let fp = dlsym(h, cstr!("pa_context_get_sink_info_by_name"));
                 if fp.is_null() {
@@ -335,6 +419,20 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_GET_SOURCE_INFO_BY_INDEX = {
+                let fp = dlsym(h, cstr!("pa_context_get_source_info_by_index"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
+            PA_CONTEXT_GET_SOURCE_INFO_BY_NAME = {
+                let fp = dlsym(h, cstr!("pa_context_get_source_info_by_name"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_GET_SOURCE_INFO_LIST = {
                 let fp = dlsym(h, cstr!("pa_context_get_source_info_list"));
                 if fp.is_null() {
@@ -349,6 +447,13 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_GET_SERVER_PROTOCOL_VERSION = {
+                let fp = dlsym(h, cstr!("pa_context_get_server_protocol_version"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_NEW = {
                 let fp = dlsym(h, cstr!("pa_context_new"));
                 if fp.is_null() {
@@ -356,6 +461,13 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_NEW_WITH_PROPLIST = {
+                let fp = dlsym(h, cstr!("pa_context_new_with_proplist"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_RTTIME_NEW = {
                 let fp = dlsym(h, cstr!("pa_context_rttime_new"));
                 if fp.is_null() {
@@ -363,6 +475,13 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_SET_CARD_PROFILE_BY_INDEX = {
+                let fp = dlsym(h, cstr!("pa_context_set_card_profile_by_index"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_SET_SINK_INPUT_VOLUME = {
                 let fp = dlsym(h, cstr!("pa_context_set_sink_input_volume"));
                 if fp.is_null() {
@@ -370,6 +489,20 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_CONTEXT_SET_SINK_PORT_BY_NAME = {
+                let fp = dlsym(h, cstr!("pa_context_set_sink_port_by_name"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
+            PA_CONTEXT_SET_SOURCE_PORT_BY_NAME = {
+                let fp = dlsym(h, cstr!("pa_context_set_source_port_by_name"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_CONTEXT_SET_STATE_CALLBACK = {
                 let fp = dlsym(h, cstr!("pa_context_set_state_callback"));
                 if fp.is_null() {
@@ -496,6 +629,27 @@ mod dynamic_fns {
                 }
                 fp
             };
+            PA_PROPLIST_NEW = {
+                let fp = dlsym(h, cstr!("pa_proplist_new"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
+            PA_PROPLIST_FREE = {
+                let fp = dlsym(h, cstr!("pa_proplist_free"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
+            PA_PROPLIST_SETS = {
+                let fp = dlsym(h, cstr!("pa_proplist_sets"));
+                if fp.is_null() {
+                    return None;
+                }
+                fp
+            };
             PA_RTCLOCK_NOW = {
                 let fp = dlsym(h, cstr!("pa_rtclock_now"));
                 if fp.is_null() {
@@ -895,6 +1049,38 @@ mod dynamic_fns {
         >(PA_CONTEXT_DRAIN))(c, cb, userdata)
     }
 
+    static mut PA_CONTEXT_GET_CARD_INFO_BY_INDEX: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_card_info_by_index(
+        c: *mut pa_context,
+        idx: u32,
+        cb: pa_card_info_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                u32,
+                pa_card_info_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_GET_CARD_INFO_BY_INDEX))(c, idx, cb, userdata)
+    }
+
+    static mut PA_CONTEXT_GET_CARD_INFO_LIST: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_card_info_list(
+        c: *mut pa_context,
+        cb: pa_card_info_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(*mut pa_context, pa_card_info_cb_t, *mut c_void) -> *mut pa_operation,
+        >(PA_CONTEXT_GET_CARD_INFO_LIST))(c, cb, userdata)
+    }
+
     static mut PA_CONTEXT_GET_SERVER_INFO: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_get_server_info(
@@ -908,6 +1094,25 @@ mod dynamic_fns {
         >(PA_CONTEXT_GET_SERVER_INFO))(c, cb, userdata)
     }
 
+    static mut PA_CONTEXT_GET_SINK_INFO_BY_INDEX: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_sink_info_by_index(
+        c: *mut pa_context,
+        idx: u32,
+        cb: pa_sink_info_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                u32,
+                pa_sink_info_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_GET_SINK_INFO_BY_INDEX))(c, idx, cb, userdata)
+    }
+
     static mut PA_CONTEXT_GET_SINK_INFO_BY_NAME: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_get_sink_info_by_name(
@@ -959,6 +1164,44 @@ mod dynamic_fns {
         >(PA_CONTEXT_GET_SINK_INPUT_INFO))(c, idx, cb, userdata)
     }
 
+    static mut PA_CONTEXT_GET_SOURCE_INFO_BY_INDEX: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_source_info_by_index(
+        c: *mut pa_context,
+        idx: u32,
+        cb: pa_source_info_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                u32,
+                pa_source_info_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_GET_SOURCE_INFO_BY_INDEX))(c, idx, cb, userdata)
+    }
+
+    static mut PA_CONTEXT_GET_SOURCE_INFO_BY_NAME: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_source_info_by_name(
+        c: *mut pa_context,
+        name: *const c_char,
+        cb: pa_source_info_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                *const c_char,
+                pa_source_info_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_GET_SOURCE_INFO_BY_NAME))(c, name, cb, userdata)
+    }
+
     static mut PA_CONTEXT_GET_SOURCE_INFO_LIST: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_get_source_info_list(
@@ -980,6 +1223,14 @@ mod dynamic_fns {
         ))(c)
     }
 
+    static mut PA_CONTEXT_GET_SERVER_PROTOCOL_VERSION: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_get_server_protocol_version(c: *const pa_context) -> u32 {
+        (::std::mem::transmute::<_, extern "C" fn(*const pa_context) -> u32>(
+            PA_CONTEXT_GET_SERVER_PROTOCOL_VERSION,
+        ))(c)
+    }
+
     static mut PA_CONTEXT_NEW: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_new(
@@ -992,6 +1243,23 @@ mod dynamic_fns {
         >(PA_CONTEXT_NEW))(mainloop, name)
     }
 
+    static mut PA_CONTEXT_NEW_WITH_PROPLIST: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_new_with_proplist(
+        mainloop: *mut pa_mainloop_api,
+        name: *const c_char,
+        proplist: *const pa_proplist,
+    ) -> *mut pa_context {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_mainloop_api,
+                *const c_char,
+                *const pa_proplist,
+            ) -> *mut pa_context,
+        >(PA_CONTEXT_NEW_WITH_PROPLIST))(mainloop, name, proplist)
+    }
+
     static mut PA_CONTEXT_RTTIME_NEW: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_rttime_new(
@@ -1011,6 +1279,27 @@ mod dynamic_fns {
         >(PA_CONTEXT_RTTIME_NEW))(c, usec, cb, userdata)
     }
 
+    static mut PA_CONTEXT_SET_CARD_PROFILE_BY_INDEX: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_set_card_profile_by_index(
+        c: *mut pa_context,
+        idx: u32,
+        profile: *const c_char,
+        cb: pa_context_success_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                u32,
+                *const c_char,
+                pa_context_success_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_SET_CARD_PROFILE_BY_INDEX))(c, idx, profile, cb, userdata)
+    }
+
     static mut PA_CONTEXT_SET_SINK_INPUT_VOLUME: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_set_sink_input_volume(
@@ -1032,6 +1321,48 @@ mod dynamic_fns {
         >(PA_CONTEXT_SET_SINK_INPUT_VOLUME))(c, idx, volume, cb, userdata)
     }
 
+    static mut PA_CONTEXT_SET_SINK_PORT_BY_NAME: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_set_sink_port_by_name(
+        c: *mut pa_context,
+        name: *const c_char,
+        port: *const c_char,
+        cb: pa_context_success_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                *const c_char,
+                *const c_char,
+                pa_context_success_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_SET_SINK_PORT_BY_NAME))(c, name, port, cb, userdata)
+    }
+
+    static mut PA_CONTEXT_SET_SOURCE_PORT_BY_NAME: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_context_set_source_port_by_name(
+        c: *mut pa_context,
+        name: *const c_char,
+        port: *const c_char,
+        cb: pa_context_success_cb_t,
+        userdata: *mut c_void,
+    ) -> *mut pa_operation {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(
+                *mut pa_context,
+                *const c_char,
+                *const c_char,
+                pa_context_success_cb_t,
+                *mut c_void,
+            ) -> *mut pa_operation,
+        >(PA_CONTEXT_SET_SOURCE_PORT_BY_NAME))(c, name, port, cb, userdata)
+    }
+
     static mut PA_CONTEXT_SET_STATE_CALLBACK: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_context_set_state_callback(
@@ -1209,6 +1540,31 @@ mod dynamic_fns {
         ))(p, key)
     }
 
+    static mut PA_PROPLIST_NEW: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_proplist_new() -> *mut pa_proplist {
+        (::std::mem::transmute::<_, extern "C" fn() -> *mut pa_proplist>(PA_PROPLIST_NEW))()
+    }
+
+    static mut PA_PROPLIST_FREE: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_proplist_free(p: *mut pa_proplist) {
+        (::std::mem::transmute::<_, extern "C" fn(*mut pa_proplist)>(PA_PROPLIST_FREE))(p)
+    }
+
+    static mut PA_PROPLIST_SETS: *mut ::libc::c_void = 0 as *mut _;
+    #[inline]
+    pub unsafe fn pa_proplist_sets(
+        p: *mut pa_proplist,
+        key: *const c_char,
+        value: *const c_char,
+    ) -> c_int {
+        (::std::mem::transmute::<
+            _,
+            extern "C" fn(*mut pa_proplist, *const c_char, *const c_char) -> c_int,
+        >(PA_PROPLIST_SETS))(p, key, value)
+    }
+
     static mut PA_RTCLOCK_NOW: *mut ::libc::c_void = 0 as *mut _;
     #[inline]
     pub unsafe fn pa_rtclock_now() -> pa_usec_t {
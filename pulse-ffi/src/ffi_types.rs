@@ -5,6 +5,9 @@ use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
 /* automatically generated by rust-bindgen */
 pub const PA_RATE_MAX: u32 = 48000 * 8;
 
+// From pulse/def.h
+pub const PA_INVALID_INDEX: u32 = u32::MAX;
+
 pub const PA_SAMPLE_U8: c_int = 0;
 pub const PA_SAMPLE_ALAW: c_int = 1;
 pub const PA_SAMPLE_ULAW: c_int = 2;
@@ -510,6 +513,13 @@ pub const PA_PROP_TYPE_STRING_ARRAY: c_int = 4;
 pub const PA_PROP_TYPE_INVALID: c_int = -1;
 pub type pa_prop_type_t = c_int;
 
+pub const PA_PROP_APPLICATION_NAME: &str = "application.name";
+pub const PA_PROP_APPLICATION_ID: &str = "application.id";
+pub const PA_PROP_APPLICATION_VERSION: &str = "application.version";
+pub const PA_PROP_APPLICATION_ICON_NAME: &str = "application.icon_name";
+pub const PA_PROP_APPLICATION_PROCESS_ID: &str = "application.process.id";
+pub const PA_PROP_APPLICATION_PROCESS_BINARY: &str = "application.process.binary";
+
 pub enum pa_operation {}
 pub type pa_operation_notify_cb_t =
     Option<unsafe extern "C" fn(o: *mut pa_operation, userdata: *mut c_void)>;
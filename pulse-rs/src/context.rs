@@ -80,12 +80,35 @@ impl Context {
         }
     }
 
+    pub fn new_with_proplist<'a, OPT>(
+        api: &MainloopApi,
+        name: OPT,
+        proplist: &Proplist,
+    ) -> Option<Self>
+    where
+        OPT: Into<Option<&'a CStr>>,
+    {
+        let ptr = unsafe {
+            ffi::pa_context_new_with_proplist(api.raw_mut(), name.unwrap_cstr(), proplist.raw_mut())
+        };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Context(ptr))
+        }
+    }
+
     #[doc(hidden)]
     #[allow(clippy::mut_from_ref)]
     pub fn raw_mut(&self) -> &mut ffi::pa_context {
         unsafe { &mut *self.0 }
     }
 
+    /// Takes another reference on the context, to be dropped with `unref`.
+    pub fn add_ref(&self) -> Self {
+        Context(unsafe { ffi::pa_context_ref(self.raw_mut()) })
+    }
+
     pub fn unref(self) {
         unsafe {
             ffi::pa_context_unref(self.raw_mut());
@@ -131,6 +154,10 @@ impl Context {
             .expect("pa_context_get_state returned invalid ContextState")
     }
 
+    pub fn get_server_protocol_version(&self) -> u32 {
+        unsafe { ffi::pa_context_get_server_protocol_version(self.raw_mut()) }
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn connect<'a, OPT>(
         &self,
@@ -183,6 +210,74 @@ impl Context {
         )
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_card_info_by_index<CB>(
+        &self,
+        idx: u32,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, *const CardInfo, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            info: *const ffi::pa_card_info,
+            eol: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, *const CardInfo, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, info, eol, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_get_card_info_by_index(
+                self.raw_mut(),
+                idx,
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_card_info_list<CB>(&self, _: CB, userdata: *mut c_void) -> Result<Operation>
+    where
+        CB: Fn(&Context, *const CardInfo, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            info: *const ffi::pa_card_info,
+            eol: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, *const CardInfo, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, info, eol, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_get_card_info_list(self.raw_mut(), Some(wrapped::<CB>), userdata)
+        )
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn rttime_new<CB>(
         &self,
@@ -244,6 +339,45 @@ impl Context {
         )
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_sink_info_by_index<CB>(
+        &self,
+        idx: u32,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, *const SinkInfo, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            info: *const ffi::pa_sink_info,
+            eol: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, *const SinkInfo, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, info, eol, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_get_sink_info_by_index(
+                self.raw_mut(),
+                idx,
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn get_sink_info_by_name<'str, CS, CB>(
         &self,
@@ -347,6 +481,85 @@ impl Context {
         )
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_source_info_by_index<CB>(
+        &self,
+        idx: u32,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, *const SourceInfo, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            info: *const ffi::pa_source_info,
+            eol: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, *const SourceInfo, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, info, eol, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_get_source_info_by_index(
+                self.raw_mut(),
+                idx,
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_source_info_by_name<'str, CS, CB>(
+        &self,
+        name: CS,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, *const SourceInfo, i32, *mut c_void),
+        CS: Into<Option<&'str CStr>>,
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            info: *const ffi::pa_source_info,
+            eol: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, *const SourceInfo, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, info, eol, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_get_source_info_by_name(
+                self.raw_mut(),
+                name.into().unwrap_cstr(),
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn get_source_info_list<CB>(&self, _: CB, userdata: *mut c_void) -> Result<Operation>
     where
@@ -376,6 +589,46 @@ impl Context {
         )
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn set_card_profile_by_index<CB>(
+        &self,
+        idx: u32,
+        profile: &CStr,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            success: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, success, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_set_card_profile_by_index(
+                self.raw_mut(),
+                idx,
+                profile.as_ptr(),
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn set_sink_input_volume<CB>(
         &self,
@@ -416,6 +669,86 @@ impl Context {
         )
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn set_sink_port_by_name<CB>(
+        &self,
+        name: &CStr,
+        port: &CStr,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            success: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, success, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_set_sink_port_by_name(
+                self.raw_mut(),
+                name.as_ptr(),
+                port.as_ptr(),
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn set_source_port_by_name<CB>(
+        &self,
+        name: &CStr,
+        port: &CStr,
+        _: CB,
+        userdata: *mut c_void,
+    ) -> Result<Operation>
+    where
+        CB: Fn(&Context, i32, *mut c_void),
+    {
+        assert_eq!(::std::mem::size_of::<CB>(), 0);
+
+        // See: A note about `wrapped` functions
+        unsafe extern "C" fn wrapped<F>(
+            c: *mut ffi::pa_context,
+            success: c_int,
+            userdata: *mut c_void,
+        ) where
+            F: Fn(&Context, i32, *mut c_void),
+        {
+            let ctx = context::from_raw_ptr(c);
+            let cb = MaybeUninit::<F>::uninit();
+            (*cb.as_ptr())(&ctx, success, userdata);
+            #[allow(clippy::forget_non_drop)]
+            forget(ctx);
+        }
+
+        op_or_err!(
+            self,
+            ffi::pa_context_set_source_port_by_name(
+                self.raw_mut(),
+                name.as_ptr(),
+                port.as_ptr(),
+                Some(wrapped::<CB>),
+                userdata
+            )
+        )
+    }
+
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn subscribe<CB>(
         &self,
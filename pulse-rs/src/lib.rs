@@ -20,6 +20,9 @@ mod util;
 pub use context::Context;
 pub use error::ErrorCode;
 pub use ffi::pa_buffer_attr as BufferAttr;
+pub use ffi::pa_card_info as CardInfo;
+pub use ffi::pa_card_port_info as CardPortInfo;
+pub use ffi::pa_card_profile_info2 as CardProfileInfo;
 pub use ffi::pa_channel_map as ChannelMap;
 pub use ffi::pa_cvolume as CVolume;
 pub use ffi::pa_sample_spec as SampleSpec;
@@ -163,6 +166,7 @@ impl From<OperationState> for ffi::pa_operation_state_t {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy)]
     pub struct ContextFlags: u32 {
         const NOAUTOSPAWN = ffi::PA_CONTEXT_NOAUTOSPAWN;
         const NOFAIL = ffi::PA_CONTEXT_NOFAIL;
@@ -441,6 +445,7 @@ pub enum SinkState {
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy)]
     pub struct SourceFlags: u32 {
         const HW_VOLUME_CTRL = ffi::PA_SOURCE_HW_VOLUME_CTRL;
         const LATENCY = ffi::PA_SOURCE_LATENCY;
@@ -588,6 +593,25 @@ impl CVolumeExt for CVolume {
     }
 }
 
+pub trait CardInfoExt {
+    fn profiles(&self) -> Vec<&CardProfileInfo>;
+    fn active_profile(&self) -> Option<&CardProfileInfo>;
+}
+
+impl CardInfoExt for CardInfo {
+    fn profiles(&self) -> Vec<&CardProfileInfo> {
+        if self.profiles2.is_null() {
+            return Vec::new();
+        }
+        let profiles =
+            unsafe { std::slice::from_raw_parts(self.profiles2, self.n_profiles as usize) };
+        profiles.iter().map(|&p| unsafe { &*p }).collect()
+    }
+    fn active_profile(&self) -> Option<&CardProfileInfo> {
+        unsafe { self.active_profile2.as_ref() }
+    }
+}
+
 pub trait ChannelMapExt {
     fn init() -> ChannelMap;
     fn init_auto(ch: u32, def: ffi::pa_channel_map_def_t) -> Option<ChannelMap>;
@@ -621,6 +645,12 @@ pub trait ProplistExt {
     fn proplist(&self) -> Proplist;
 }
 
+impl ProplistExt for CardInfo {
+    fn proplist(&self) -> Proplist {
+        unsafe { proplist::from_raw_ptr(self.proplist) }
+    }
+}
+
 impl ProplistExt for SinkInfo {
     fn proplist(&self) -> Proplist {
         unsafe { proplist::from_raw_ptr(self.proplist) }
@@ -5,11 +5,35 @@
 
 use ffi;
 use std::ffi::{CStr, CString};
+use ErrorCode;
+use Result;
 
 #[derive(Debug)]
 pub struct Proplist(*mut ffi::pa_proplist);
 
 impl Proplist {
+    /// Creates an empty property list, to be released with `free`.
+    pub fn new() -> Option<Self> {
+        let ptr = unsafe { ffi::pa_proplist_new() };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Proplist(ptr))
+        }
+    }
+
+    #[doc(hidden)]
+    #[allow(clippy::mut_from_ref)]
+    pub fn raw_mut(&self) -> &mut ffi::pa_proplist {
+        unsafe { &mut *self.0 }
+    }
+
+    pub fn free(self) {
+        unsafe {
+            ffi::pa_proplist_free(self.0);
+        }
+    }
+
     pub fn gets<T>(&self, key: T) -> Option<&CStr>
     where
         T: Into<Vec<u8>>,
@@ -25,6 +49,19 @@ impl Proplist {
             Some(unsafe { CStr::from_ptr(r) })
         }
     }
+
+    pub fn sets<K, V>(&self, key: K, value: V) -> Result<()>
+    where
+        K: Into<Vec<u8>>,
+        V: Into<Vec<u8>>,
+    {
+        let (key, value) = match (CString::new(key), CString::new(value)) {
+            (Ok(k), Ok(v)) => (k, v),
+            _ => return Err(ErrorCode::from_error_code(ffi::PA_ERR_INVALID)),
+        };
+        let r = unsafe { ffi::pa_proplist_sets(self.0, key.as_ptr(), value.as_ptr()) };
+        error_result!((), r)
+    }
 }
 
 pub unsafe fn from_raw_ptr(raw: *mut ffi::pa_proplist) -> Proplist {
@@ -0,0 +1,154 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use pulse::CardProfileInfo;
+use pulse_ffi::PA_PORT_AVAILABLE_NO;
+use std::ffi::CString;
+
+/// Cards whose profile was switched so that input streams could be opened
+/// on them, with the profile to restore once the last of those streams is
+/// destroyed.
+#[derive(Debug, Default)]
+pub struct CardProfiles {
+    held: Vec<HeldProfile>,
+}
+
+#[derive(Debug)]
+struct HeldProfile {
+    card: u32,
+    previous: CString,
+    streams: usize,
+}
+
+impl CardProfiles {
+    pub fn new() -> Self {
+        CardProfiles::default()
+    }
+
+    /// Takes another reference on `card` if its profile is already held.
+    pub fn retain(&mut self, card: u32) -> bool {
+        match self.held.iter_mut().find(|h| h.card == card) {
+            Some(h) => {
+                h.streams += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records that `card` was switched away from `previous` for one stream.
+    pub fn insert(&mut self, card: u32, previous: CString) {
+        debug_assert!(!self.held.iter().any(|h| h.card == card));
+        self.held.push(HeldProfile {
+            card,
+            previous,
+            streams: 1,
+        });
+    }
+
+    /// Drops a reference on `card`, returning the profile to restore when
+    /// it was the last one.
+    pub fn release(&mut self, card: u32) -> Option<CString> {
+        let i = self.held.iter().position(|h| h.card == card)?;
+        self.held[i].streams -= 1;
+        if self.held[i].streams == 0 {
+            Some(self.held.swap_remove(i).previous)
+        } else {
+            None
+        }
+    }
+}
+
+/// Picks the profile to switch to so that a card provides an input: the
+/// highest priority available profile with both sinks and sources (e.g. a
+/// Bluetooth headset's head unit profile).
+pub fn input_profile<'a, I>(profiles: I) -> Option<&'a CardProfileInfo>
+where
+    I: IntoIterator<Item = &'a CardProfileInfo>,
+{
+    profiles
+        .into_iter()
+        .filter(|p| p.n_sinks > 0 && p.n_sources > 0 && p.available != PA_PORT_AVAILABLE_NO)
+        .max_by_key(|p| p.priority)
+}
+
+/// The profiles to switch a card from and to so that it provides an input,
+/// if its `active` profile has none, such as a Bluetooth headset in its A2DP
+/// profile.
+pub fn input_switch<'a, I>(
+    active: Option<&'a CardProfileInfo>,
+    profiles: I,
+) -> Option<(&'a CardProfileInfo, &'a CardProfileInfo)>
+where
+    I: IntoIterator<Item = &'a CardProfileInfo>,
+{
+    let active = active.filter(|p| p.n_sources == 0)?;
+    Some((active, input_profile(profiles)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{input_profile, input_switch, CardProfiles};
+    use pulse::CardProfileInfo;
+    use pulse_ffi::{PA_PORT_AVAILABLE_NO, PA_PORT_AVAILABLE_YES};
+    use std::ffi::CString;
+
+    fn profile(n_sinks: u32, n_sources: u32, priority: u32, available: i32) -> CardProfileInfo {
+        CardProfileInfo {
+            n_sinks,
+            n_sources,
+            priority,
+            available,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn picks_duplex_profile() {
+        let a2dp = profile(1, 0, 40, PA_PORT_AVAILABLE_YES);
+        let hsp = profile(1, 1, 30, PA_PORT_AVAILABLE_YES);
+        let hfp = profile(1, 1, 20, PA_PORT_AVAILABLE_YES);
+        let off = profile(0, 0, 0, PA_PORT_AVAILABLE_YES);
+        let profiles = [a2dp, hfp, hsp, off];
+        let picked = input_profile(&profiles).unwrap();
+        assert_eq!(picked.priority, 30);
+    }
+
+    #[test]
+    fn skips_unavailable_profiles() {
+        let profiles = [
+            profile(1, 0, 40, PA_PORT_AVAILABLE_YES),
+            profile(1, 1, 30, PA_PORT_AVAILABLE_NO),
+        ];
+        assert!(input_profile(&profiles).is_none());
+    }
+
+    #[test]
+    fn switch_a2dp_card() {
+        let a2dp = profile(1, 0, 40, PA_PORT_AVAILABLE_YES);
+        let hfp = profile(1, 1, 20, PA_PORT_AVAILABLE_YES);
+        let profiles = [a2dp, hfp];
+
+        let (from, to) = input_switch(Some(&profiles[0]), &profiles).unwrap();
+        assert_eq!((from.priority, to.priority), (40, 20));
+        // Already providing an input.
+        assert!(input_switch(Some(&profiles[1]), &profiles).is_none());
+        assert!(input_switch(None, &profiles).is_none());
+        // A2DP only.
+        assert!(input_switch(Some(&profiles[0]), &profiles[..1]).is_none());
+    }
+
+    #[test]
+    fn restore_after_last_stream() {
+        let mut held = CardProfiles::new();
+        assert!(!held.retain(3));
+        held.insert(3, CString::new("a2dp_sink").unwrap());
+        assert!(held.retain(3));
+        assert_eq!(held.release(3), None);
+        assert_eq!(held.release(3), Some(CString::new("a2dp_sink").unwrap()));
+        assert_eq!(held.release(3), None);
+        assert!(!held.retain(3));
+    }
+}
@@ -3,30 +3,98 @@
 // This program is made available under an ISC-style license.  See the
 // accompanying file LICENSE for details.
 
+use backend::card_profile::{self, CardProfiles};
+use backend::deadline::Deadline;
+use backend::device_cache::{DeviceCache, DeviceInfos, DeviceNames, DeviceSnapshot};
+use backend::held_ports::{HeldPorts, Retain};
+use backend::server_caps::ServerCapabilities;
+use backend::shared::SharedConnection;
 use backend::*;
 use cubeb_backend::{
     ffi, log_enabled, Context, ContextOps, DeviceCollectionRef, DeviceId, DeviceType, Error,
     InputProcessingParams, Ops, Result, Stream, StreamParams, StreamParamsRef,
 };
-use pulse::{self, ProplistExt};
+use pulse::{self, CardInfoExt, ProplistExt};
 use pulse_ffi::*;
 use semver;
 use std::cell::RefCell;
 use std::default::Default;
 use std::ffi::{CStr, CString};
 use std::mem;
-use std::os::raw::c_void;
-use std::ptr;
+use std::os::raw::{c_char, c_void};
+use std::time::Duration;
+use std::{ptr, slice};
 
 #[derive(Debug)]
-pub struct DefaultInfo {
+pub struct DefaultInfo<F = pulse::SinkFlags> {
     pub sample_spec: pulse::SampleSpec,
     pub channel_map: pulse::ChannelMap,
-    pub flags: pulse::SinkFlags,
+    pub flags: F,
+    pub latency: pa_usec_t,
+    pub configured_latency: pa_usec_t,
 }
 
 pub const PULSE_OPS: Ops = capi_new!(PulseContext, PulseStream);
 
+/// Called on the mainloop thread when the default device of `devtype`
+/// changes, with its previous and new IDs, as reported by enumeration.
+/// `old_device` is only valid during the call, `new_device` for as long as
+/// it remains the default.
+pub type DefaultDeviceChangedCallback = unsafe extern "C" fn(
+    context: *mut ffi::cubeb,
+    devtype: ffi::cubeb_device_type,
+    old_device: ffi::cubeb_devid,
+    new_device: ffi::cubeb_devid,
+    user_ptr: *mut c_void,
+);
+
+/// How the context connects to the server.
+#[derive(Debug)]
+pub struct ConnectOptions {
+    /// Server string such as `unix:/run/user/1000/pulse/native` or
+    /// `tcp:localhost`. `None` lets libpulse pick, honoring `PULSE_SERVER`
+    /// and client.conf.
+    pub server: Option<CString>,
+    pub flags: pulse::ContextFlags,
+    /// How long to wait for the context to become ready. `None` waits
+    /// forever.
+    pub connect_timeout: Option<Duration>,
+    /// How long to wait for each operation on the server, such as a query
+    /// or a stream becoming ready, before cancelling it. `None` waits
+    /// forever.
+    pub operation_timeout: Option<Duration>,
+    /// Application identity shown by mixers such as pavucontrol, along with
+    /// the context name, process ID and binary: a reverse domain name such
+    /// as `org.example.App`, an icon from the XDG icon theme and a version.
+    pub application_id: Option<CString>,
+    pub application_icon_name: Option<CString>,
+    pub application_version: Option<CString>,
+    /// Share one mainloop thread and connection with the other contexts
+    /// created with this set, as long as they connect to the same server
+    /// with the same flags. The first one's identity and timeouts are used
+    /// to connect.
+    pub shared: bool,
+    /// Connect on the first call that needs the server, rather than when the
+    /// context is created. Connection failures are reported by that call.
+    pub lazy: bool,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            server: None,
+            flags: pulse::ContextFlags::empty(),
+            connect_timeout: None,
+            operation_timeout: None,
+            application_id: None,
+            application_icon_name: None,
+            application_version: None,
+            shared: false,
+            lazy: false,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct PulseContext {
@@ -34,22 +102,36 @@ pub struct PulseContext {
     pub mainloop: pulse::ThreadedMainloop,
     pub context: Option<pulse::Context>,
     pub default_sink_info: Option<DefaultInfo>,
+    pub default_source_info: Option<DefaultInfo<pulse::SourceFlags>>,
+    // The queries for the above started by `server_info_cb`.
+    default_info_ops: Vec<pulse::Operation>,
     pub context_name: Option<CString>,
+    connect_options: ConnectOptions,
     pub input_collection_changed_callback: ffi::cubeb_device_collection_changed_callback,
     pub input_collection_changed_user_ptr: *mut c_void,
     pub output_collection_changed_callback: ffi::cubeb_device_collection_changed_callback,
     pub output_collection_changed_user_ptr: *mut c_void,
+    default_device_changed_callback: Option<DefaultDeviceChangedCallback>,
+    default_device_changed_user_ptr: *mut c_void,
     pub error: bool,
-    pub version_2_0_0: bool,
+    pub server: ServerCapabilities,
     pub version_0_9_8: bool,
+    hide_monitor_sources: bool,
+    // Types of devices enumerated, kept up to date so that the results can
+    // be cached.
+    enumerated_types: DeviceType,
+    pub switch_bluetooth_profiles: bool,
     #[cfg(feature = "pulse-dlopen")]
     pub libpulse: LibLoader,
-    devids: RefCell<Intern>,
+    card_profiles: RefCell<CardProfiles>,
+    held_ports: RefCell<HeldPorts>,
+    device_cache: RefCell<DeviceCache>,
+    shared: Option<*mut SharedConnection>,
 }
 
 impl PulseContext {
     #[cfg(feature = "pulse-dlopen")]
-    fn _new(name: Option<CString>) -> Result<Box<Self>> {
+    fn _new(name: Option<CString>, connect_options: ConnectOptions) -> Result<Box<Self>> {
         let libpulse = unsafe { open() };
         if libpulse.is_none() {
             cubeb_log!("libpulse not found");
@@ -62,36 +144,58 @@ impl PulseContext {
             mainloop: pulse::ThreadedMainloop::new(),
             context: None,
             default_sink_info: None,
+            default_source_info: None,
+            default_info_ops: Vec::new(),
             context_name: name,
+            connect_options,
             input_collection_changed_callback: None,
             input_collection_changed_user_ptr: ptr::null_mut(),
             output_collection_changed_callback: None,
             output_collection_changed_user_ptr: ptr::null_mut(),
+            default_device_changed_callback: None,
+            default_device_changed_user_ptr: ptr::null_mut(),
             error: true,
             version_0_9_8: false,
-            version_2_0_0: false,
-            devids: RefCell::new(Intern::new()),
+            server: ServerCapabilities::default(),
+            hide_monitor_sources: false,
+            enumerated_types: DeviceType::empty(),
+            switch_bluetooth_profiles: false,
+            card_profiles: RefCell::new(CardProfiles::new()),
+            held_ports: RefCell::new(HeldPorts::new()),
+            device_cache: RefCell::default(),
+            shared: None,
         });
 
         Ok(ctx)
     }
 
     #[cfg(not(feature = "pulse-dlopen"))]
-    fn _new(name: Option<CString>) -> Result<Box<Self>> {
+    fn _new(name: Option<CString>, connect_options: ConnectOptions) -> Result<Box<Self>> {
         Ok(Box::new(PulseContext {
             _ops: &PULSE_OPS,
             mainloop: pulse::ThreadedMainloop::new(),
             context: None,
             default_sink_info: None,
+            default_source_info: None,
+            default_info_ops: Vec::new(),
             context_name: name,
+            connect_options,
             input_collection_changed_callback: None,
             input_collection_changed_user_ptr: ptr::null_mut(),
             output_collection_changed_callback: None,
             output_collection_changed_user_ptr: ptr::null_mut(),
+            default_device_changed_callback: None,
+            default_device_changed_user_ptr: ptr::null_mut(),
             error: true,
             version_0_9_8: false,
-            version_2_0_0: false,
-            devids: RefCell::new(Intern::new()),
+            server: ServerCapabilities::default(),
+            hide_monitor_sources: false,
+            enumerated_types: DeviceType::empty(),
+            switch_bluetooth_profiles: false,
+            card_profiles: RefCell::new(CardProfiles::new()),
+            held_ports: RefCell::new(HeldPorts::new()),
+            device_cache: RefCell::default(),
+            shared: None,
         }))
     }
 
@@ -105,17 +209,77 @@ impl PulseContext {
                     sample_spec: info.sample_spec,
                     channel_map: info.channel_map,
                     flags,
+                    latency: info.latency,
+                    configured_latency: info.configured_latency,
+                });
+                if let Some(name) = try_cstr_from(info.name) {
+                    let id = active_port_id(name, info.ports, info.n_ports, info.active_port);
+                    ctx.default_device_changed(DeviceType::OUTPUT, &id);
+                }
+            }
+            ctx.mainloop.signal();
+        }
+
+        fn source_info_cb(
+            _: &pulse::Context,
+            i: *const pulse::SourceInfo,
+            eol: i32,
+            u: *mut c_void,
+        ) {
+            let ctx = unsafe { &mut *(u as *mut PulseContext) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                let flags = pulse::SourceFlags::from_bits_truncate(info.flags);
+                ctx.default_source_info = Some(DefaultInfo {
+                    sample_spec: info.sample_spec,
+                    channel_map: info.channel_map,
+                    flags,
+                    latency: info.latency,
+                    configured_latency: info.configured_latency,
                 });
+                if let Some(name) = try_cstr_from(info.name) {
+                    let id = active_port_id(name, info.ports, info.n_ports, info.active_port);
+                    ctx.default_device_changed(DeviceType::INPUT, &id);
+                }
             }
             ctx.mainloop.signal();
         }
 
         if let Some(info) = info {
-            let _ = context.get_sink_info_by_name(
+            let ctx = unsafe { &mut *(u as *mut PulseContext) };
+            ctx.server = ServerCapabilities::new(
+                try_cstr_from(info.server_name).unwrap_or_default(),
+                try_cstr_from(info.server_version).unwrap_or_default(),
+                context.get_server_protocol_version(),
+                unsafe { CStr::from_ptr(pulse::library_version()) },
+            );
+            let changed = ctx.device_cache.borrow_mut().update_defaults(
+                try_cstr_from(info.default_sink_name),
+                try_cstr_from(info.default_source_name),
+            );
+            if !changed.is_empty() {
+                cubeb_log!("Default device changed");
+                ctx.device_cache.borrow_mut().invalidate(changed);
+                ctx.collection_changed(changed);
+            }
+
+            let mut ops = Vec::new();
+            if let Ok(o) = context.get_sink_info_by_name(
                 try_cstr_from(info.default_sink_name),
                 sink_info_cb,
                 u,
-            );
+            ) {
+                ops.push(o);
+            }
+            if let Ok(o) = context.get_source_info_by_name(
+                try_cstr_from(info.default_source_name),
+                source_info_cb,
+                u,
+            ) {
+                ops.push(o);
+            }
+            // Those of an earlier server change are done with.
+            ctx.default_info_ops = ops;
         } else {
             // If info is None, then an error occured.
             let ctx = unsafe { &mut *(u as *mut PulseContext) };
@@ -123,117 +287,302 @@ impl PulseContext {
         }
     }
 
-    fn new(name: Option<&CStr>) -> Result<Box<Self>> {
+    fn new(name: Option<&CStr>, connect_options: ConnectOptions) -> Result<Box<Self>> {
         let name = name.map(|s| s.to_owned());
-        let mut ctx = PulseContext::_new(name)?;
+        let mut ctx = PulseContext::_new(name, connect_options)?;
 
-        if ctx.mainloop.start().is_err() {
+        if ctx.connect_options.shared {
+            let ctx_ptr: *mut PulseContext = ctx.as_mut();
+            ctx.shared = SharedConnection::join(ctx_ptr, &ctx.connect_options);
+        }
+        if let Some(shared) = ctx.shared {
+            ctx.mainloop = unsafe { &*shared }.mainloop();
+        } else if ctx.mainloop.start().is_err() {
             ctx.destroy();
             cubeb_log!("Error: couldn't start pulse's mainloop");
             return Err(Error::error());
         }
 
+        if ctx.connect_options.lazy {
+            return Ok(ctx);
+        }
+
         if ctx.context_init().is_err() {
             ctx.destroy();
             cubeb_log!("Error: couldn't init pulse's context");
             return Err(Error::error());
         }
 
-        ctx.mainloop.lock();
-        /* server_info_callback performs a second async query,
-         * which is responsible for initializing default_sink_info
-         * and signalling the mainloop to end the wait. */
-        let user_data: *mut c_void = ctx.as_mut() as *mut _ as *mut _;
-        if let Some(ref context) = ctx.context {
-            if let Ok(o) = context.get_server_info(PulseContext::server_info_cb, user_data) {
-                ctx.operation_wait(None, &o);
-            }
-        }
-        ctx.mainloop.unlock();
-
-        /* Update `default_sink_info` when the default device changes. */
-        if let Err(e) = ctx.subscribe_notifications(pulse::SubscriptionMask::SERVER) {
-            cubeb_log!("subscribe_notifications ignored failure: {}", e);
-        }
-
         // Return the result.
         Ok(ctx)
     }
 
     pub fn destroy(&mut self) {
         self.context_destroy();
+        self.device_cache
+            .borrow_mut()
+            .release_orphans(DeviceType::all());
 
         assert!(
             self.input_collection_changed_callback.is_none()
                 && self.output_collection_changed_callback.is_none()
         );
 
-        if !self.mainloop.is_null() {
+        if let Some(shared) = self.shared.take() {
+            // The mainloop is the shared connection's.
+            mem::forget(mem::take(&mut self.mainloop));
+            SharedConnection::leave(shared, self);
+        } else if !self.mainloop.is_null() {
             self.mainloop.stop();
         }
     }
 
-    fn subscribe_notifications(&mut self, mask: pulse::SubscriptionMask) -> Result<()> {
-        fn update_collection(
-            _: &pulse::Context,
-            event: pulse::SubscriptionEvent,
-            index: u32,
-            user_data: *mut c_void,
-        ) {
-            let ctx = unsafe { &mut *(user_data as *mut PulseContext) };
+    fn sink_changed(_: &pulse::Context, i: *const pulse::SinkInfo, eol: i32, u: *mut c_void) {
+        let ctx = unsafe { &mut *(u as *mut PulseContext) };
+        if eol == 0 {
+            let info = unsafe { &*i };
+            let snapshot =
+                DeviceSnapshot::new(info.description, info.ports, info.n_ports, info.active_port);
+            if ctx
+                .device_cache
+                .borrow_mut()
+                .update(DeviceType::OUTPUT, info.index, snapshot)
+            {
+                cubeb_log!("Sink index {} changed", info.index);
+                ctx.collection_changed(DeviceType::OUTPUT);
+            }
+            ctx.default_port_changed(
+                DeviceType::OUTPUT,
+                info.name,
+                info.ports,
+                info.n_ports,
+                info.active_port,
+            );
+        } else {
+            ctx.mainloop.signal();
+        }
+    }
 
-            let (f, t) = (event.event_facility(), event.event_type());
-            if (f == pulse::SubscriptionEventFacility::Source)
-                | (f == pulse::SubscriptionEventFacility::Sink)
+    fn source_changed(_: &pulse::Context, i: *const pulse::SourceInfo, eol: i32, u: *mut c_void) {
+        let ctx = unsafe { &mut *(u as *mut PulseContext) };
+        if eol == 0 {
+            let info = unsafe { &*i };
+            let snapshot =
+                DeviceSnapshot::new(info.description, info.ports, info.n_ports, info.active_port);
+            if ctx
+                .device_cache
+                .borrow_mut()
+                .update(DeviceType::INPUT, info.index, snapshot)
             {
-                if (t == pulse::SubscriptionEventType::Remove)
-                    | (t == pulse::SubscriptionEventType::New)
-                {
-                    if log_enabled() {
-                        let op = if t == pulse::SubscriptionEventType::New {
-                            "Adding"
-                        } else {
-                            "Removing"
-                        };
-                        let dev = if f == pulse::SubscriptionEventFacility::Sink {
-                            "sink"
-                        } else {
-                            "source "
-                        };
-                        cubeb_log!("{} {} index {}", op, dev, index);
-                    }
+                cubeb_log!("Source index {} changed", info.index);
+                ctx.collection_changed(DeviceType::INPUT);
+            }
+            ctx.default_port_changed(
+                DeviceType::INPUT,
+                info.name,
+                info.ports,
+                info.n_ports,
+                info.active_port,
+            );
+        } else {
+            ctx.mainloop.signal();
+        }
+    }
 
-                    if f == pulse::SubscriptionEventFacility::Source {
-                        unsafe {
-                            ctx.input_collection_changed_callback.unwrap()(
-                                ctx as *mut _ as *mut _,
-                                ctx.input_collection_changed_user_ptr,
-                            );
-                        }
-                    }
-                    if f == pulse::SubscriptionEventFacility::Sink {
-                        unsafe {
-                            ctx.output_collection_changed_callback.unwrap()(
-                                ctx as *mut _ as *mut _,
-                                ctx.output_collection_changed_user_ptr,
-                            );
-                        }
-                    }
-                }
-            } else if (f == pulse::SubscriptionEventFacility::Server)
-                && (t == pulse::SubscriptionEventType::Change)
+    fn collection_changed(&mut self, devtype: DeviceType) {
+        let ctx_ptr = self as *mut _ as *mut _;
+        if devtype.contains(DeviceType::INPUT) {
+            if let Some(cb) = self.input_collection_changed_callback {
+                unsafe { cb(ctx_ptr, self.input_collection_changed_user_ptr) };
+            }
+        }
+        if devtype.contains(DeviceType::OUTPUT) {
+            if let Some(cb) = self.output_collection_changed_callback {
+                unsafe { cb(ctx_ptr, self.output_collection_changed_user_ptr) };
+            }
+        }
+    }
+
+    /* Sink and source events keep the device cache up to date and drive the
+     * device collection changed callbacks. Server events update
+     * `default_sink_info` and `default_source_info` when the default devices
+     * change. */
+    pub fn subscription_event(
+        context: &pulse::Context,
+        event: pulse::SubscriptionEvent,
+        index: u32,
+        user_data: *mut c_void,
+    ) {
+        let ctx = unsafe { &mut *(user_data as *mut PulseContext) };
+
+        let (f, t) = (event.event_facility(), event.event_type());
+        if (f == pulse::SubscriptionEventFacility::Source)
+            | (f == pulse::SubscriptionEventFacility::Sink)
+        {
+            let devtype = if f == pulse::SubscriptionEventFacility::Sink {
+                DeviceType::OUTPUT
+            } else {
+                DeviceType::INPUT
+            };
+            if t == pulse::SubscriptionEventType::Remove {
+                ctx.device_cache.borrow_mut().remove(devtype, index);
+            }
+            if !ctx.device_cache.borrow().is_tracking(devtype) {
+                return;
+            }
+
+            if (t == pulse::SubscriptionEventType::Remove)
+                | (t == pulse::SubscriptionEventType::New)
             {
-                cubeb_log!("Server changed {}", index as i32);
-                let user_data: *mut c_void = ctx as *mut _ as *mut _;
-                if let Some(ref context) = ctx.context {
-                    if let Err(e) = context.get_server_info(PulseContext::server_info_cb, user_data)
-                    {
-                        cubeb_log!("Error: get_server_info ignored failure: {}", e);
-                    }
+                if log_enabled() {
+                    let op = if t == pulse::SubscriptionEventType::New {
+                        "Adding"
+                    } else {
+                        "Removing"
+                    };
+                    let dev = if f == pulse::SubscriptionEventFacility::Sink {
+                        "sink"
+                    } else {
+                        "source "
+                    };
+                    cubeb_log!("{} {} index {}", op, dev, index);
+                }
+
+                ctx.collection_changed(devtype);
+            }
+
+            // Any event can change what enumeration reports, down to the
+            // latency, so don't try to patch the cached enumeration.
+            ctx.device_cache.borrow_mut().invalidate(devtype);
+
+            // Cache new devices, and compare changed ones against the cache.
+            if t != pulse::SubscriptionEventType::Remove {
+                let r = if devtype == DeviceType::OUTPUT {
+                    context.get_sink_info_by_index(index, PulseContext::sink_changed, user_data)
+                } else {
+                    context.get_source_info_by_index(index, PulseContext::source_changed, user_data)
+                };
+                if let Err(e) = r {
+                    cubeb_log!("Error: get device info ignored failure: {}", e);
                 }
             }
+        } else if (f == pulse::SubscriptionEventFacility::Server)
+            && (t == pulse::SubscriptionEventType::Change)
+        {
+            cubeb_log!("Server changed {}", index as i32);
+            let user_data: *mut c_void = ctx as *mut _ as *mut _;
+            if let Some(ref context) = ctx.context {
+                if let Err(e) = context.get_server_info(PulseContext::server_info_cb, user_data) {
+                    cubeb_log!("Error: get_server_info ignored failure: {}", e);
+                }
+            }
+        }
+    }
+
+    // Records the ID of the device `name` if it's the default one of
+    // `devtype`: a change of its active port changes the ID without changing
+    // the server's defaults.
+    fn default_port_changed(
+        &mut self,
+        devtype: DeviceType,
+        name: *const c_char,
+        ports: *mut *mut pa_port_info,
+        n_ports: u32,
+        active_port: *mut pa_port_info,
+    ) {
+        let name = match try_cstr_from(name) {
+            Some(name) if self.device_cache.borrow().is_default(devtype, name) => name,
+            _ => return,
+        };
+        let id = active_port_id(name, ports, n_ports, active_port);
+        self.default_device_changed(devtype, &id);
+    }
+
+    // Records the ID of the default device of `devtype`, reporting a change.
+    fn default_device_changed(&mut self, devtype: DeviceType, id: &CStr) {
+        let changed = self
+            .device_cache
+            .borrow_mut()
+            .update_default_id(devtype, id);
+        if let Some((previous, current)) = changed {
+            cubeb_log!("Default {:?} device changed to {:?}", devtype, id);
+            if let Some(cb) = self.default_device_changed_callback {
+                let ctx_ptr = self as *mut _ as *mut _;
+                unsafe {
+                    cb(
+                        ctx_ptr,
+                        devtype.bits(),
+                        previous as ffi::cubeb_devid,
+                        current as ffi::cubeb_devid,
+                        self.default_device_changed_user_ptr,
+                    )
+                };
+            }
+            self.device_cache.borrow_mut().release_id(previous);
+        }
+    }
+
+    /// Sets the callback told about default device changes, or clears it
+    /// with `None`.
+    pub fn set_default_device_changed_callback(
+        &mut self,
+        cb: Option<DefaultDeviceChangedCallback>,
+        user_ptr: *mut c_void,
+    ) -> Result<()> {
+        let previous = self.subscription_mask();
+        let previous_callback = (
+            self.default_device_changed_callback,
+            self.default_device_changed_user_ptr,
+        );
+        // The mainloop thread calls it.
+        self.mainloop.lock();
+        self.default_device_changed_callback = cb;
+        self.default_device_changed_user_ptr = user_ptr;
+        self.mainloop.unlock();
+
+        let mask = self.subscription_mask();
+        if self.context.is_some() && mask.bits() != previous.bits() {
+            if let Err(e) = self.subscribe_notifications(mask) {
+                self.mainloop.lock();
+                (
+                    self.default_device_changed_callback,
+                    self.default_device_changed_user_ptr,
+                ) = previous_callback;
+                self.mainloop.unlock();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Events to subscribe to, for the devices enumerated since the device
+    /// collection changed callbacks were last all unregistered, and those a
+    /// callback is registered for. The default device changed callback
+    /// needs both types, to see port changes. None once all the callbacks
+    /// are unregistered.
+    pub fn subscription_mask(&self) -> pulse::SubscriptionMask {
+        let mut devtype = self.enumerated_types;
+        if self.default_device_changed_callback.is_some() {
+            devtype |= DeviceType::OUTPUT | DeviceType::INPUT;
+        }
+        if self.output_collection_changed_callback.is_some() {
+            devtype |= DeviceType::OUTPUT;
+        }
+        if self.input_collection_changed_callback.is_some() {
+            devtype |= DeviceType::INPUT;
+        }
+        device_events_mask(devtype)
+    }
+
+    /* Subscribes to the events handled by `subscription_event`. */
+    fn subscribe_device_events(&mut self) {
+        let mask = self.subscription_mask();
+        if let Err(e) = self.subscribe_notifications(mask) {
+            cubeb_log!("subscribe_notifications ignored failure: {}", e);
         }
+    }
 
+    fn subscribe_notifications(&mut self, mask: pulse::SubscriptionMask) -> Result<()> {
         fn success(_: &pulse::Context, success: i32, user_data: *mut c_void) {
             let ctx = unsafe { &*(user_data as *mut PulseContext) };
             if success != 1 {
@@ -246,16 +595,55 @@ impl PulseContext {
         if let Some(ref context) = self.context {
             self.mainloop.lock();
 
-            context.set_subscribe_callback(update_collection, user_data);
+            match self.shared {
+                Some(shared) => context
+                    .set_subscribe_callback(SharedConnection::subscription_event, shared as *mut _),
+                None => context.set_subscribe_callback(PulseContext::subscription_event, user_data),
+            }
 
-            if let Ok(o) = context.subscribe(mask, success, self as *const _ as *mut _) {
-                self.operation_wait(None, &o);
-            } else {
+            let sinks = mask.contains(pulse::SubscriptionMask::SINK);
+            let sources = mask.contains(pulse::SubscriptionMask::SOURCE);
+            let mut tracking = DeviceType::empty();
+            if sinks {
+                tracking |= DeviceType::OUTPUT;
+            }
+            if sources {
+                tracking |= DeviceType::INPUT;
+            }
+            self.device_cache.borrow_mut().set_tracking(tracking);
+
+            // A shared connection has a single subscription, covering the
+            // events of every member.
+            let mask = match self.shared {
+                Some(shared) => unsafe { &*shared }.subscription_mask(),
+                None => mask,
+            };
+            let subscribed = match context.subscribe(mask, success, self as *const _ as *mut _) {
+                Ok(o) => self.operation_wait(None, &o),
+                Err(_) => false,
+            };
+            if !subscribed {
+                self.device_cache
+                    .borrow_mut()
+                    .set_tracking(DeviceType::empty());
                 self.mainloop.unlock();
                 cubeb_log!("Error: context subscribe failed");
                 return Err(Error::error());
             }
 
+            // Snapshot the devices so that later changes can be compared.
+            if sinks {
+                if let Ok(o) = context.get_sink_info_list(PulseContext::sink_changed, user_data) {
+                    self.operation_wait(None, &o);
+                }
+            }
+            if sources {
+                if let Ok(o) = context.get_source_info_list(PulseContext::source_changed, user_data)
+                {
+                    self.operation_wait(None, &o);
+                }
+            }
+
             self.mainloop.unlock();
         }
 
@@ -263,10 +651,19 @@ impl PulseContext {
     }
 }
 
+impl PulseContext {
+    pub fn init_with_options(
+        context_name: Option<&CStr>,
+        connect_options: ConnectOptions,
+    ) -> Result<Context> {
+        let ctx = PulseContext::new(context_name, connect_options)?;
+        Ok(unsafe { Context::from_ptr(Box::into_raw(ctx) as *mut _) })
+    }
+}
+
 impl ContextOps for PulseContext {
     fn init(context_name: Option<&CStr>) -> Result<Context> {
-        let ctx = PulseContext::new(context_name)?;
-        Ok(unsafe { Context::from_ptr(Box::into_raw(ctx) as *mut _) })
+        PulseContext::init_with_options(context_name, ConnectOptions::default())
     }
 
     fn backend_id(&mut self) -> &'static CStr {
@@ -278,6 +675,7 @@ impl ContextOps for PulseContext {
     }
 
     fn max_channel_count(&mut self) -> Result<u32> {
+        self.ensure_connected()?;
         match self.default_sink_info {
             Some(ref info) => Ok(u32::from(info.channel_map.channels)),
             None => {
@@ -288,11 +686,16 @@ impl ContextOps for PulseContext {
     }
 
     fn min_latency(&mut self, params: StreamParams) -> Result<u32> {
-        // According to PulseAudio developers, this is a safe minimum.
-        Ok(25 * params.rate() / 1000)
+        self.ensure_connected()?;
+        Ok(min_latency_frames(
+            self.default_sink_info.as_ref(),
+            self.server.fallback_min_latency(),
+            params.rate(),
+        ))
     }
 
     fn preferred_sample_rate(&mut self) -> Result<u32> {
+        self.ensure_connected()?;
         match self.default_sink_info {
             Some(ref info) => Ok(info.sample_spec.rate),
             None => {
@@ -330,46 +733,77 @@ impl ContextOps for PulseContext {
 
             let info = unsafe { &*i };
 
-            let group_id = match info.proplist().gets("sysfs.path") {
-                Some(p) => p.to_owned().into_raw(),
-                _ => ptr::null_mut(),
-            };
-
-            let vendor_name = match info.proplist().gets("device.vendor.name") {
-                Some(p) => p.to_owned().into_raw(),
-                _ => ptr::null_mut(),
-            };
-
             let info_name = unsafe { CStr::from_ptr(info.name) };
             let info_description = unsafe { CStr::from_ptr(info.description) }.to_owned();
 
+            let group_id = sink_group_id(info);
+            // Remembered so that the sink's monitor source is grouped with it.
+            list_data
+                .sink_group_ids
+                .push((info.index, group_id.clone()));
+            let group_id = Some(group_id);
+
+            let vendor_name = info
+                .proplist()
+                .gets("device.vendor.name")
+                .map(|p| p.to_owned());
+
             let preferred = if *info_name == *list_data.default_sink_name {
                 ffi::CUBEB_DEVICE_PREF_ALL
             } else {
                 ffi::CUBEB_DEVICE_PREF_NONE
             };
 
-            let device_id = ctx.devids.borrow_mut().add(info_name);
-            let friendly_name = info_description.into_raw();
+            let flags = pulse::SinkFlags::from_bits_truncate(info.flags);
+            let (latency_lo, latency_hi) = device_latency_frames(
+                info.latency,
+                info.configured_latency,
+                flags.contains(pulse::SinkFlags::LATENCY),
+                flags.contains(pulse::SinkFlags::DYNAMIC_LATENCY),
+                ctx.server.fallback_min_latency(),
+                info.sample_spec.rate,
+            );
+
+            let (format, default_format) = device_formats(info.sample_spec.format);
+            let default_rate = device_default_rate(info.sample_spec.rate, list_data.server_rate);
+
+            let device_id =
+                ctx.device_cache
+                    .borrow_mut()
+                    .intern_id(DeviceType::OUTPUT, info.index, info_name);
+            let names = DeviceNames {
+                friendly_name: info_description,
+                group_id,
+                vendor_name,
+            };
             let devinfo = ffi::cubeb_device_info {
                 device_id,
                 devid: device_id as ffi::cubeb_devid,
-                friendly_name,
-                group_id,
-                vendor_name,
+                friendly_name: ptr::null(),
+                group_id: ptr::null(),
+                vendor_name: ptr::null(),
                 device_type: ffi::CUBEB_DEVICE_TYPE_OUTPUT,
                 state: ctx.state_from_port(info.active_port),
                 preferred,
-                format: ffi::CUBEB_DEVICE_FMT_ALL,
-                default_format: pulse_format_to_cubeb_format(info.sample_spec.format),
+                format,
+                default_format,
                 max_channels: u32::from(info.channel_map.channels),
+                // The server resamples any rate.
                 min_rate: 1,
                 max_rate: PA_RATE_MAX,
-                default_rate: info.sample_spec.rate,
-                latency_lo: 0,
-                latency_hi: 0,
+                default_rate,
+                latency_lo,
+                latency_hi,
             };
-            list_data.devinfo.push(devinfo);
+            list_data.push_device(
+                devinfo,
+                names,
+                info.index,
+                info_name,
+                info.ports,
+                info.n_ports,
+                info.active_port,
+            );
         }
 
         fn add_input_device(
@@ -391,18 +825,38 @@ impl ContextOps for PulseContext {
 
             let info = unsafe { &*i };
 
-            let group_id = match info.proplist().gets("sysfs.path") {
-                Some(p) => p.to_owned().into_raw(),
-                _ => ptr::null_mut(),
-            };
-
-            let vendor_name = match info.proplist().gets("device.vendor.name") {
-                Some(p) => p.to_owned().into_raw(),
-                _ => ptr::null_mut(),
-            };
+            let monitor = is_monitor_source(info);
+            if monitor && ctx.hide_monitor_sources {
+                return;
+            }
 
             let info_name = unsafe { CStr::from_ptr(info.name) };
-            let info_description = unsafe { CStr::from_ptr(info.description) }.to_owned();
+            let mut info_description = unsafe { CStr::from_ptr(info.description) }.to_owned();
+
+            let mut group_id = info.proplist().gets("sysfs.path").map(|p| p.to_owned());
+
+            if monitor {
+                if !info_description.to_bytes().starts_with(b"Monitor of ") {
+                    let mut name = b"Monitor of ".to_vec();
+                    name.extend_from_slice(info_description.to_bytes());
+                    info_description = CString::new(name).expect("description contains no NUL");
+                }
+
+                // Group the monitor with the sink it captures.
+                if let Some(sink_group_id) = list_data
+                    .sink_group_ids
+                    .iter()
+                    .find(|(index, _)| *index == info.monitor_of_sink)
+                    .map(|(_, group_id)| group_id.clone())
+                {
+                    group_id = Some(sink_group_id);
+                }
+            }
+
+            let vendor_name = info
+                .proplist()
+                .gets("device.vendor.name")
+                .map(|p| p.to_owned());
 
             let preferred = if *info_name == *list_data.default_source_name {
                 ffi::CUBEB_DEVICE_PREF_ALL
@@ -410,28 +864,78 @@ impl ContextOps for PulseContext {
                 ffi::CUBEB_DEVICE_PREF_NONE
             };
 
-            let device_id = ctx.devids.borrow_mut().add(info_name);
-            let friendly_name = info_description.into_raw();
+            let flags = pulse::SourceFlags::from_bits_truncate(info.flags);
+            let (latency_lo, latency_hi) = device_latency_frames(
+                info.latency,
+                info.configured_latency,
+                flags.contains(pulse::SourceFlags::LATENCY),
+                flags.contains(pulse::SourceFlags::DYNAMIC_LATENCY),
+                ctx.server.fallback_min_latency(),
+                info.sample_spec.rate,
+            );
+
+            let (format, default_format) = device_formats(info.sample_spec.format);
+            let default_rate = device_default_rate(info.sample_spec.rate, list_data.server_rate);
+
+            let device_id =
+                ctx.device_cache
+                    .borrow_mut()
+                    .intern_id(DeviceType::INPUT, info.index, info_name);
+            let names = DeviceNames {
+                friendly_name: info_description,
+                group_id,
+                vendor_name,
+            };
             let devinfo = ffi::cubeb_device_info {
                 device_id,
                 devid: device_id as ffi::cubeb_devid,
-                friendly_name,
-                group_id,
-                vendor_name,
+                friendly_name: ptr::null(),
+                group_id: ptr::null(),
+                vendor_name: ptr::null(),
                 device_type: ffi::CUBEB_DEVICE_TYPE_INPUT,
                 state: ctx.state_from_port(info.active_port),
                 preferred,
-                format: ffi::CUBEB_DEVICE_FMT_ALL,
-                default_format: pulse_format_to_cubeb_format(info.sample_spec.format),
+                format,
+                default_format,
                 max_channels: u32::from(info.channel_map.channels),
+                // The server resamples any rate.
                 min_rate: 1,
                 max_rate: PA_RATE_MAX,
-                default_rate: info.sample_spec.rate,
-                latency_lo: 0,
-                latency_hi: 0,
+                default_rate,
+                latency_lo,
+                latency_hi,
             };
 
-            list_data.devinfo.push(devinfo);
+            list_data.push_device(
+                devinfo,
+                names,
+                info.index,
+                info_name,
+                info.ports,
+                info.n_ports,
+                info.active_port,
+            );
+        }
+
+        fn add_sink_group_id(
+            _: &pulse::Context,
+            i: *const pulse::SinkInfo,
+            eol: i32,
+            user_data: *mut c_void,
+        ) {
+            let list_data = unsafe { &mut *(user_data as *mut PulseDevListData) };
+
+            if eol != 0 {
+                list_data.context.mainloop.signal();
+                return;
+            }
+
+            debug_assert!(!i.is_null());
+
+            let info = unsafe { &*i };
+            list_data
+                .sink_group_ids
+                .push((info.index, sink_group_id(info)));
         }
 
         fn default_device_names(
@@ -448,74 +952,116 @@ impl ContextOps for PulseContext {
                 list_data.default_source_name = super::try_cstr_from(info.default_source_name)
                     .map(|s| s.to_owned())
                     .unwrap_or_default();
+                list_data.server_rate = info.sample_spec.rate;
             }
 
             list_data.context.mainloop.signal();
         }
 
-        let mut user_data = PulseDevListData::new(self);
+        self.ensure_connected()?;
+
+        if !self.enumerated_types.contains(devtype) {
+            let previous = self.subscription_mask();
+            self.enumerated_types |= devtype;
+            let mask = self.subscription_mask();
+            if mask.bits() != previous.bits() && self.subscribe_notifications(mask).is_err() {
+                // Enumerate without caching.
+                cubeb_log!("Couldn't subscribe to device events, not caching devices");
+            }
+        }
+
+        let mut devices = DeviceInfos::new();
 
         if let Some(ref context) = self.context {
             self.mainloop.lock();
 
-            if let Ok(o) =
-                context.get_server_info(default_device_names, &mut user_data as *mut _ as *mut _)
-            {
-                self.operation_wait(None, &o);
-            }
+            let mut user_data = PulseDevListData::new(self);
+            let user_data_ptr = &mut user_data as *mut _ as *mut _;
+            let mut have_defaults = false;
+            let mut have_sink_group_ids = false;
+            let mut complete = true;
 
-            if devtype.contains(DeviceType::OUTPUT) {
-                if let Ok(o) = context
-                    .get_sink_info_list(add_output_device, &mut user_data as *mut _ as *mut _)
-                {
-                    self.operation_wait(None, &o);
+            for t in [DeviceType::OUTPUT, DeviceType::INPUT] {
+                if !devtype.contains(t) {
+                    continue;
                 }
-            }
 
-            if devtype.contains(DeviceType::INPUT) {
-                if let Ok(o) = context
-                    .get_source_info_list(add_input_device, &mut user_data as *mut _ as *mut _)
-                {
-                    self.operation_wait(None, &o);
+                // Checked right before use: events handled while waiting on
+                // the server may have invalidated it.
+                let cached = self.device_cache.borrow_mut().enumerated(t);
+                if let Some(mut cached) = cached {
+                    devices.append(&mut cached);
+                    continue;
+                }
+
+                if !have_defaults {
+                    complete = match context.get_server_info(default_device_names, user_data_ptr) {
+                        Ok(o) => self.operation_wait(None, &o),
+                        Err(_) => false,
+                    };
+                    if !complete {
+                        break;
+                    }
+                    have_defaults = true;
+                }
+
+                if t == DeviceType::INPUT && !have_sink_group_ids && !self.hide_monitor_sources {
+                    // The sinks weren't listed above, but monitor sources are
+                    // grouped with them.
+                    complete = match context.get_sink_info_list(add_sink_group_id, user_data_ptr) {
+                        Ok(o) => self.operation_wait(None, &o),
+                        Err(_) => false,
+                    };
+                    if !complete {
+                        break;
+                    }
+                    have_sink_group_ids = true;
+                }
+
+                let o = if t == DeviceType::OUTPUT {
+                    context.get_sink_info_list(add_output_device, user_data_ptr)
+                } else {
+                    context.get_source_info_list(add_input_device, user_data_ptr)
+                };
+                complete = match o {
+                    Ok(o) => self.operation_wait(None, &o),
+                    Err(_) => false,
+                };
+
+                let found = mem::take(&mut user_data.devinfo);
+                let mut cache = self.device_cache.borrow_mut();
+                if !complete {
+                    cache.release_devices(found);
+                    break;
+                }
+                devices.append(&mut cache.copy_devices(&found));
+                cache.set_enumerated(t, found);
+                if t == DeviceType::OUTPUT {
+                    have_sink_group_ids = true;
                 }
             }
 
+            if !complete {
+                self.device_cache.borrow_mut().release_devices(devices);
+                self.mainloop.unlock();
+                cubeb_log!("Error: couldn't enumerate devices");
+                return Err(Error::error());
+            }
+
             self.mainloop.unlock();
         }
 
-        // Extract the array of cubeb_device_info from
-        // PulseDevListData and convert it into C representation.
-        let mut tmp = Vec::new();
-        mem::swap(&mut user_data.devinfo, &mut tmp);
-        let mut devices = tmp.into_boxed_slice();
-        let coll = unsafe { &mut *collection.as_ptr() };
-        coll.device = devices.as_mut_ptr();
-        coll.count = devices.len();
-
-        // Giving away the memory owned by devices.  Don't free it!
-        mem::forget(devices);
+        devices.into_collection(unsafe { &mut *collection.as_ptr() });
         Ok(())
     }
 
     fn device_collection_destroy(&mut self, collection: &mut DeviceCollectionRef) -> Result<()> {
         debug_assert!(!collection.as_ptr().is_null());
-        unsafe {
-            let coll = &mut *collection.as_ptr();
-            let mut devices = Vec::from_raw_parts(coll.device, coll.count, coll.count);
-            for dev in &mut devices {
-                if !dev.group_id.is_null() {
-                    let _ = CString::from_raw(dev.group_id as *mut _);
-                }
-                if !dev.vendor_name.is_null() {
-                    let _ = CString::from_raw(dev.vendor_name as *mut _);
-                }
-                if !dev.friendly_name.is_null() {
-                    let _ = CString::from_raw(dev.friendly_name as *mut _);
-                }
-            }
-            coll.device = ptr::null_mut();
-            coll.count = 0;
-        }
+        let devices = unsafe { DeviceInfos::from_collection(&mut *collection.as_ptr()) };
+
+        self.mainloop.lock();
+        self.device_cache.borrow_mut().release_devices(devices);
+        self.mainloop.unlock();
         Ok(())
     }
 
@@ -534,6 +1080,8 @@ impl ContextOps for PulseContext {
     ) -> Result<Stream> {
         if self.error {
             self.context_init()?;
+        } else {
+            self.refresh_server_info()?;
         }
 
         let stm = PulseStream::new(
@@ -557,6 +1105,20 @@ impl ContextOps for PulseContext {
         cb: ffi::cubeb_device_collection_changed_callback,
         user_ptr: *mut c_void,
     ) -> Result<()> {
+        if cb.is_some() {
+            self.ensure_connected()?;
+        }
+
+        let previous = self.subscription_mask();
+        let previous_callbacks = (
+            self.input_collection_changed_callback,
+            self.input_collection_changed_user_ptr,
+            self.output_collection_changed_callback,
+            self.output_collection_changed_user_ptr,
+        );
+        let previous_enumerated = self.enumerated_types;
+        // The mainloop thread calls them.
+        self.mainloop.lock();
         if devtype.contains(DeviceType::INPUT) {
             self.input_collection_changed_callback = cb;
             self.input_collection_changed_user_ptr = user_ptr;
@@ -565,19 +1127,32 @@ impl ContextOps for PulseContext {
             self.output_collection_changed_callback = cb;
             self.output_collection_changed_user_ptr = user_ptr;
         }
-
-        let mut mask = pulse::SubscriptionMask::empty();
-        if self.input_collection_changed_callback.is_some() {
-            mask |= pulse::SubscriptionMask::SOURCE;
-        }
-        if self.output_collection_changed_callback.is_some() {
-            mask |= pulse::SubscriptionMask::SINK;
+        if self.input_collection_changed_callback.is_none()
+            && self.output_collection_changed_callback.is_none()
+        {
+            // Nobody to tell about changes: the devices stop being cached,
+            // and events stop once nothing else needs them.
+            self.enumerated_types = DeviceType::empty();
         }
-        /* Default device changed, this is always registered in order to update the
-         * `default_sink_info` when the default device changes. */
-        mask |= pulse::SubscriptionMask::SERVER;
+        self.mainloop.unlock();
 
-        self.subscribe_notifications(mask)
+        let mask = self.subscription_mask();
+        if self.context.is_some() && mask.bits() != previous.bits() {
+            if let Err(e) = self.subscribe_notifications(mask) {
+                // Not left registered without the events to call it.
+                self.mainloop.lock();
+                (
+                    self.input_collection_changed_callback,
+                    self.input_collection_changed_user_ptr,
+                    self.output_collection_changed_callback,
+                    self.output_collection_changed_user_ptr,
+                ) = previous_callbacks;
+                self.enumerated_types = previous_enumerated;
+                self.mainloop.unlock();
+                return Err(e);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -588,88 +1163,216 @@ impl Drop for PulseContext {
 }
 
 impl PulseContext {
-    /* Initialize PulseAudio Context */
-    fn context_init(&mut self) -> Result<()> {
-        fn error_state(c: &pulse::Context, u: *mut c_void) {
-            let ctx = unsafe { &mut *(u as *mut PulseContext) };
-            if !c.get_state().is_good() {
-                ctx.error = true;
-            }
-            ctx.mainloop.signal();
+    /// Connects to the server if that was deferred with
+    /// `ConnectOptions::lazy`, or if the previous attempt failed.
+    pub fn ensure_connected(&mut self) -> Result<()> {
+        if self.context.is_none() {
+            return self.context_init();
         }
+        self.refresh_server_info()
+    }
 
-        if self.context.is_some() {
-            debug_assert!(self.error);
-            self.context_destroy();
+    /// Queries the server's info and that of the default devices again if
+    /// it isn't kept up to date by server events, which are only
+    /// subscribed to while callbacks need them.
+    fn refresh_server_info(&mut self) -> Result<()> {
+        if self.error
+            || self
+                .subscription_mask()
+                .contains(pulse::SubscriptionMask::SERVER)
+        {
+            return Ok(());
         }
+        self.mainloop.lock();
+        let have_server_info = self.query_server_info();
+        self.mainloop.unlock();
+        if !have_server_info {
+            cubeb_log!("Error: couldn't get the server's info");
+            return Err(Error::error());
+        }
+        Ok(())
+    }
 
-        self.context = {
-            let name = self.context_name.as_ref().map(|s| s.as_ref());
-            pulse::Context::new(&self.mainloop.get_api(), name)
+    // Queries the server's info and, through `server_info_cb`, that of the
+    // default sink and source, waiting for all of them. Must be called with
+    // the mainloop locked.
+    fn query_server_info(&mut self) -> bool {
+        let context_ptr: *mut c_void = self as *mut _ as *mut _;
+        let have_server_info = match self.context {
+            Some(ref context) => {
+                match context.get_server_info(PulseContext::server_info_cb, context_ptr) {
+                    Ok(o) => self.operation_wait(None, &o),
+                    Err(_) => false,
+                }
+            }
+            None => false,
         };
+        have_server_info
+            && mem::take(&mut self.default_info_ops)
+                .iter()
+                .all(|o| self.operation_wait(None, o))
+    }
 
-        let context_ptr: *mut c_void = self as *mut _ as *mut _;
-        if self.context.is_none() {
-            cubeb_log!("Error: couldn't create pulse's context");
-            return Err(Error::error());
+    /* Initialize PulseAudio Context */
+    fn context_init(&mut self) -> Result<()> {
+        let reconnect = self.context.is_some();
+        if reconnect {
+            debug_assert!(self.error);
+            self.context_destroy();
         }
 
+        // Device indices and state don't survive a reconnection.
+        self.device_cache.borrow_mut().reset();
+
         self.mainloop.lock();
-        let connected = if let Some(ref context) = self.context {
-            context.set_state_callback(error_state, context_ptr);
-            context
-                .connect(None, pulse::ContextFlags::empty(), ptr::null())
-                .is_ok()
-        } else {
-            false
+        let adopted = self.shared.and_then(|shared| unsafe { &*shared }.context());
+        let connected = match adopted {
+            // The shared connection is up, connected by another member.
+            Some(context) => {
+                self.context = Some(context);
+                true
+            }
+            None => self.context_connect(),
         };
 
-        if !connected || !self.wait_until_context_ready() {
+        if !connected {
             self.mainloop.unlock();
             self.context_destroy();
             cubeb_log!("Error: error while waiting for pulse's context to be ready");
             return Err(Error::error());
         }
 
+        let have_server_info = self.query_server_info();
         self.mainloop.unlock();
 
+        if !have_server_info {
+            self.context_destroy();
+            cubeb_log!("Error: couldn't get the server's info");
+            return Err(Error::error());
+        }
+        cubeb_log!(
+            "Connected to {:?} server version {:?}, protocol {}",
+            self.server.kind,
+            self.server.version,
+            self.server.protocol_version
+        );
+
         let version_str = unsafe { CStr::from_ptr(pulse::library_version()) };
         if let Ok(version) = semver::Version::parse(&version_str.to_string_lossy()) {
             self.version_0_9_8 =
                 version >= semver::Version::parse("0.9.8").expect("Failed to parse version");
-            self.version_2_0_0 =
-                version >= semver::Version::parse("2.0.0").expect("Failed to parse version");
         }
 
         self.error = false;
 
+        self.subscribe_device_events();
+
         Ok(())
     }
 
+    // Creates and connects a new context, waiting for it to be ready. Must
+    // be called with the mainloop locked.
+    fn context_connect(&mut self) -> bool {
+        fn error_state(c: &pulse::Context, u: *mut c_void) {
+            let ctx = unsafe { &mut *(u as *mut PulseContext) };
+            if !c.get_state().is_good() {
+                ctx.error = true;
+            }
+            ctx.mainloop.signal();
+        }
+
+        self.context = {
+            let name = self.context_name.as_deref();
+            let api = self.mainloop.get_api();
+            match pulse::Proplist::new() {
+                Some(proplist) => {
+                    for (key, value) in application_properties(name, &self.connect_options) {
+                        if proplist.sets(key, value).is_err() {
+                            cubeb_log!("Error: couldn't set the {} property", key);
+                        }
+                    }
+                    let context = pulse::Context::new_with_proplist(&api, name, &proplist);
+                    proplist.free();
+                    context
+                }
+                None => pulse::Context::new(&api, name),
+            }
+        };
+
+        let context_ptr: *mut c_void = self as *mut _ as *mut _;
+        let context = match self.context {
+            Some(ref context) => context,
+            None => {
+                cubeb_log!("Error: couldn't create pulse's context");
+                return false;
+            }
+        };
+
+        match self.shared {
+            Some(shared) => {
+                context.set_state_callback(SharedConnection::context_state, shared as *mut _)
+            }
+            None => context.set_state_callback(error_state, context_ptr),
+        }
+        let connected = context
+            .connect(
+                self.connect_options.server.as_deref(),
+                self.connect_options.flags,
+                ptr::null(),
+            )
+            .is_ok();
+        if !connected || !self.wait_until_context_ready() {
+            if self.shared.is_some() {
+                // Not shared yet, so left to this member to disconnect.
+                context.clear_state_callback();
+                context.disconnect();
+            }
+            return false;
+        }
+
+        if let Some(shared) = self.shared {
+            unsafe { &mut *shared }.set_context(context);
+        }
+        true
+    }
+
     fn context_destroy(&mut self) {
         fn drain_complete(_: &pulse::Context, u: *mut c_void) {
             let ctx = unsafe { &*(u as *mut PulseContext) };
             ctx.mainloop.signal();
         }
 
+        if self.context.is_none() {
+            return;
+        }
+
         let context_ptr: *mut c_void = self as *mut _ as *mut _;
+        self.mainloop.lock();
+        self.default_info_ops.clear();
         if let Some(ctx) = self.context.take() {
-            self.mainloop.lock();
-            if let Ok(o) = ctx.drain(drain_complete, context_ptr) {
-                self.operation_wait(None, &o);
+            // A shared connection is only dropped, the last member tears it
+            // down. Draining it would wait on the other members' operations.
+            if self.shared.is_none() {
+                if let Ok(o) = ctx.drain(drain_complete, context_ptr) {
+                    self.operation_wait(None, &o);
+                }
+                ctx.clear_state_callback();
+                ctx.disconnect();
             }
-            ctx.clear_state_callback();
-            ctx.disconnect();
             ctx.unref();
-            self.mainloop.unlock();
         }
+        self.mainloop.unlock();
     }
 
+    /// Waits for `o` to complete, giving up on it when `s` or the context
+    /// fail or when the operation timeout passes. Returns whether it
+    /// completed.
     pub fn operation_wait<'a, S>(&self, s: S, o: &pulse::Operation) -> bool
     where
         S: Into<Option<&'a pulse::Stream>>,
     {
         let stream = s.into();
+        let deadline = self.operation_deadline();
         while o.get_state() == PA_OPERATION_RUNNING {
             self.mainloop.wait();
             if let Some(ref context) = self.context {
@@ -683,13 +1386,34 @@ impl PulseContext {
                     return false;
                 }
             }
+
+            if deadline.expired() && o.get_state() == PA_OPERATION_RUNNING {
+                cubeb_log!("Error: operation timed out, cancelling it");
+                o.clone().cancel();
+                return false;
+            }
         }
 
         true
     }
 
+    /// A deadline for waiting on the server, according to the operation
+    /// timeout. Must be called with the mainloop locked.
+    pub fn operation_deadline(&self) -> Deadline {
+        Deadline::new(
+            &self.mainloop,
+            self.context.as_ref(),
+            self.connect_options.operation_timeout,
+        )
+    }
+
     pub fn wait_until_context_ready(&self) -> bool {
         if let Some(ref context) = self.context {
+            let deadline = Deadline::new(
+                &self.mainloop,
+                Some(context),
+                self.connect_options.connect_timeout,
+            );
             loop {
                 let state = context.get_state();
                 if !state.is_good() {
@@ -698,6 +1422,10 @@ impl PulseContext {
                 if state == pulse::ContextState::Ready {
                     break;
                 }
+                if deadline.expired() {
+                    cubeb_log!("Error: timed out connecting to the server");
+                    return false;
+                }
                 self.mainloop.wait();
             }
         }
@@ -705,10 +1433,307 @@ impl PulseContext {
         true
     }
 
+    /// Rate and channel count of the default input device.
+    pub fn default_input_params(&mut self) -> Result<(u32, u32)> {
+        self.ensure_connected()?;
+        match self.default_source_info {
+            Some(ref info) => Ok((info.sample_spec.rate, u32::from(info.channel_map.channels))),
+            None => {
+                cubeb_log!("Error: couldn't get the default input device's parameters");
+                Err(Error::error())
+            }
+        }
+    }
+
+    /// Keeps `id` valid for a stream opened on it, if it's a device ID from
+    /// enumeration. Must be called with the mainloop locked.
+    pub fn retain_device_id(&self, id: *const c_char) -> bool {
+        self.device_cache.borrow_mut().retain_id(id)
+    }
+
+    /// Must be called with the mainloop locked.
+    pub fn release_device_id(&self, id: *const c_char) {
+        self.device_cache.borrow_mut().release_id(id);
+    }
+
+    pub fn set_hide_monitor_sources(&mut self, hide: bool) {
+        self.mainloop.lock();
+        if self.hide_monitor_sources != hide {
+            self.hide_monitor_sources = hide;
+            self.device_cache.borrow_mut().invalidate(DeviceType::INPUT);
+        }
+        self.mainloop.unlock();
+    }
+
+    /// Switches the Bluetooth card an input stream is opened on to a profile
+    /// with an input, if its active profile has none. That's the card behind
+    /// `source`, or behind the default source when `source` is `None`. When
+    /// there's no such source, as with a headset in its A2DP profile, it's
+    /// the card behind `sink`, the stream's output device, or behind the
+    /// default sink when `sink` is `None`. A card behind a source, such as a
+    /// microphone chosen as the default, is left alone. Returns the card, to
+    /// be passed to `release_input_profile` when the stream is destroyed,
+    /// and the source the card now provides.
+    ///
+    /// Must be called with the mainloop locked.
+    pub fn acquire_input_profile(
+        &self,
+        source: Option<&CStr>,
+        sink: Option<&CStr>,
+    ) -> Option<(u32, Option<CString>)> {
+        fn source_card_cb(
+            _: &pulse::Context,
+            i: *const pulse::SourceInfo,
+            eol: i32,
+            u: *mut c_void,
+        ) {
+            let query = unsafe { &mut *(u as *mut CardQuery) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                if !is_monitor_source(info) {
+                    query.card = info.card;
+                }
+            }
+            query.context.mainloop.signal();
+        }
+
+        fn sink_card_cb(_: &pulse::Context, i: *const pulse::SinkInfo, eol: i32, u: *mut c_void) {
+            let query = unsafe { &mut *(u as *mut CardQuery) };
+            if eol == 0 {
+                query.card = unsafe { (*i).card };
+            }
+            query.context.mainloop.signal();
+        }
+
+        fn card_cb(_: &pulse::Context, i: *const pulse::CardInfo, eol: i32, u: *mut c_void) {
+            let query = unsafe { &mut *(u as *mut CardQuery) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                let bluetooth = info
+                    .proplist()
+                    .gets("device.bus")
+                    .is_some_and(|b| b.to_bytes() == b"bluetooth");
+                let switch = card_profile::input_switch(info.active_profile(), info.profiles());
+                if let (true, Some((active, input))) = (bluetooth, switch) {
+                    query.previous_profile =
+                        Some(unsafe { CStr::from_ptr(active.name) }.to_owned());
+                    query.input_profile = Some(unsafe { CStr::from_ptr(input.name) }.to_owned());
+                }
+            }
+            query.context.mainloop.signal();
+        }
+
+        let context = self.context.as_ref()?;
+        let mut query = CardQuery {
+            context: self,
+            card: PA_INVALID_INDEX,
+            previous_profile: None,
+            input_profile: None,
+        };
+        let query_ptr = &mut query as *mut _ as *mut _;
+
+        if let Ok(o) = context.get_source_info_by_name(source, source_card_cb, query_ptr) {
+            self.operation_wait(None, &o);
+        }
+        if query.card == PA_INVALID_INDEX {
+            if let Ok(o) = context.get_sink_info_by_name(sink, sink_card_cb, query_ptr) {
+                self.operation_wait(None, &o);
+            }
+        }
+        let card = query.card;
+        if card == PA_INVALID_INDEX {
+            return None;
+        }
+
+        if !self.card_profiles.borrow_mut().retain(card) {
+            if let Ok(o) = context.get_card_info_by_index(card, card_cb, query_ptr) {
+                self.operation_wait(None, &o);
+            }
+            let (previous, profile) = match (query.previous_profile, query.input_profile) {
+                (Some(previous), Some(profile)) => (previous, profile),
+                _ => return None,
+            };
+
+            cubeb_log!("Switching card {} to profile {:?} for input", card, profile);
+            let self_ptr = self as *const _ as *mut _;
+            match context.set_card_profile_by_index(card, &profile, profile_success, self_ptr) {
+                Ok(o) if self.operation_wait(None, &o) => {}
+                _ => return None,
+            }
+            self.card_profiles.borrow_mut().insert(card, previous);
+        }
+
+        Some((card, self.card_source(card)))
+    }
+
+    /// Restores the profile of a card switched by `acquire_input_profile`
+    /// once no stream needs it anymore.
+    ///
+    /// Must be called with the mainloop locked.
+    pub fn release_input_profile(&self, card: u32) {
+        let previous = self.card_profiles.borrow_mut().release(card);
+        if let (Some(profile), Some(context)) = (previous, self.context.as_ref()) {
+            cubeb_log!("Restoring card {} to profile {:?}", card, profile);
+            let self_ptr = self as *const _ as *mut _;
+            if let Ok(o) =
+                context.set_card_profile_by_index(card, &profile, profile_success, self_ptr)
+            {
+                self.operation_wait(None, &o);
+            }
+        }
+    }
+
+    // Name of the first non-monitor source of `card`.
+    fn card_source(&self, card: u32) -> Option<CString> {
+        fn source_cb(_: &pulse::Context, i: *const pulse::SourceInfo, eol: i32, u: *mut c_void) {
+            let query = unsafe { &mut *(u as *mut CardSourceQuery) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                if query.source.is_none() && info.card == query.card && !is_monitor_source(info) {
+                    query.source = Some(unsafe { CStr::from_ptr(info.name) }.to_owned());
+                }
+            } else {
+                query.context.mainloop.signal();
+            }
+        }
+
+        let context = self.context.as_ref()?;
+        let mut query = CardSourceQuery {
+            context: self,
+            card,
+            source: None,
+        };
+        if let Ok(o) = context.get_source_info_list(source_cb, &mut query as *mut _ as *mut _) {
+            self.operation_wait(None, &o);
+        }
+        query.source
+    }
+
+    /// Resolves the device ID `id` of a sink or source to the device's name,
+    /// switching the device to the port the ID selects, if any. Returns the
+    /// name, and whether the device's port is held for the stream, to be
+    /// released with `release_port` when the stream is destroyed. Fails if
+    /// streams hold the device on another port.
+    ///
+    /// Must be called with the mainloop locked.
+    pub fn select_port(&self, devtype: DeviceType, id: &CStr) -> Result<(CString, bool)> {
+        // Device names may contain the separator: an existing device is taken
+        // as named, else the ID must end with one of the device's ports.
+        let (name, port) = match super::split_device_port(id) {
+            (name, Some(port)) if self.device_ports(devtype, id).is_none() => (name, port),
+            _ => return Ok((id.to_owned(), false)),
+        };
+        let active_port = match self.device_ports(devtype, &name) {
+            Some((ports, active_port)) if ports.contains(&port) => active_port,
+            // Not a device, the stream fails to connect.
+            _ => return Ok((id.to_owned(), false)),
+        };
+
+        match self.held_ports.borrow_mut().retain(devtype, &name, &port) {
+            Retain::Held => return Ok((name, true)),
+            Retain::OtherPort => {
+                cubeb_log!(
+                    "Error: {:?} is in use on another port than {:?}",
+                    name,
+                    port
+                );
+                return Err(Error::device_unavailable());
+            }
+            Retain::NotHeld => {}
+        }
+
+        // Held even when already active, so that other streams can't switch
+        // this one's port.
+        let previous = if active_port.as_ref() == Some(&port) {
+            None
+        } else {
+            if !self.set_port(devtype, &name, &port) {
+                cubeb_log!("Error: couldn't select the port {:?}", port);
+                return Err(Error::error());
+            }
+            active_port
+        };
+        self.held_ports
+            .borrow_mut()
+            .insert(devtype, &name, port, previous);
+        Ok((name, true))
+    }
+
+    /// Releases the port of a device held by `select_port`, restoring the
+    /// port it was switched away from once no stream needs it anymore.
+    ///
+    /// Must be called with the mainloop locked.
+    pub fn release_port(&self, devtype: DeviceType, device: &CStr) {
+        let previous = self.held_ports.borrow_mut().release(devtype, device);
+        if let Some(port) = previous {
+            cubeb_log!("Restoring port {:?} of {:?}", port, device);
+            self.set_port(devtype, device, &port);
+        }
+    }
+
+    fn set_port(&self, devtype: DeviceType, device: &CStr, port: &CStr) -> bool {
+        let context = match self.context {
+            Some(ref context) => context,
+            None => return false,
+        };
+        let self_ptr = self as *const _ as *mut _;
+        let o = if devtype == DeviceType::OUTPUT {
+            context.set_sink_port_by_name(device, port, port_success, self_ptr)
+        } else {
+            context.set_source_port_by_name(device, port, port_success, self_ptr)
+        };
+        match o {
+            Ok(o) => self.operation_wait(None, &o),
+            Err(_) => false,
+        }
+    }
+
+    // Names of the ports of the sink or source `name`, and of its active
+    // port, or `None` if there's no such device.
+    fn device_ports(
+        &self,
+        devtype: DeviceType,
+        name: &CStr,
+    ) -> Option<(Vec<CString>, Option<CString>)> {
+        fn sink_cb(_: &pulse::Context, i: *const pulse::SinkInfo, eol: i32, u: *mut c_void) {
+            let query = unsafe { &mut *(u as *mut PortQuery) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                query.found(info.ports, info.n_ports, info.active_port);
+            }
+            query.context.mainloop.signal();
+        }
+
+        fn source_cb(_: &pulse::Context, i: *const pulse::SourceInfo, eol: i32, u: *mut c_void) {
+            let query = unsafe { &mut *(u as *mut PortQuery) };
+            if eol == 0 {
+                let info = unsafe { &*i };
+                query.found(info.ports, info.n_ports, info.active_port);
+            }
+            query.context.mainloop.signal();
+        }
+
+        let context = self.context.as_ref()?;
+        let mut query = PortQuery {
+            context: self,
+            ports: None,
+        };
+        let query_ptr = &mut query as *mut _ as *mut _;
+        let o = if devtype == DeviceType::OUTPUT {
+            context.get_sink_info_by_name(name, sink_cb, query_ptr)
+        } else {
+            context.get_source_info_by_name(name, source_cb, query_ptr)
+        };
+        if let Ok(o) = o {
+            self.operation_wait(None, &o);
+        }
+        query.ports
+    }
+
     fn state_from_port(&self, i: *const pa_port_info) -> ffi::cubeb_device_state {
         if !i.is_null() {
             let info = unsafe { *i };
-            if self.version_2_0_0 && info.available == PA_PORT_AVAILABLE_NO {
+            if self.server.port_availability() && info.available == PA_PORT_AVAILABLE_NO {
                 ffi::CUBEB_DEVICE_STATE_UNPLUGGED
             } else {
                 ffi::CUBEB_DEVICE_STATE_ENABLED
@@ -722,7 +1747,11 @@ impl PulseContext {
 struct PulseDevListData<'a> {
     default_sink_name: CString,
     default_source_name: CString,
-    devinfo: Vec<ffi::cubeb_device_info>,
+    server_rate: u32,
+    // (sink index, group id) of the sinks listed so far
+    sink_group_ids: Vec<(u32, CString)>,
+    // Taken after each list operation completes.
+    devinfo: DeviceInfos,
     context: &'a PulseContext,
 }
 
@@ -734,27 +1763,458 @@ impl<'a> PulseDevListData<'a> {
         PulseDevListData {
             default_sink_name: CString::default(),
             default_source_name: CString::default(),
-            devinfo: Vec::new(),
+            server_rate: 0,
+            sink_group_ids: Vec::new(),
+            devinfo: DeviceInfos::new(),
             context,
         }
     }
-}
 
-impl Drop for PulseDevListData<'_> {
-    fn drop(&mut self) {
-        for elem in &mut self.devinfo {
-            let _ = unsafe { Box::from_raw(elem) };
+    // Adds `devinfo` named `names`, or when the device `index` has several
+    // ports, one entry per port whose ID selects that port when a stream is
+    // opened on it.
+    #[allow(clippy::too_many_arguments)]
+    fn push_device(
+        &mut self,
+        devinfo: ffi::cubeb_device_info,
+        names: DeviceNames,
+        index: u32,
+        name: &CStr,
+        ports: *mut *mut pa_port_info,
+        n_ports: u32,
+        active_port: *mut pa_port_info,
+    ) {
+        if ports.is_null() || n_ports < 2 {
+            self.devinfo.push(devinfo, names);
+            return;
         }
+
+        let ctx = self.context;
+        let devtype = if devinfo.device_type == ffi::CUBEB_DEVICE_TYPE_OUTPUT {
+            DeviceType::OUTPUT
+        } else {
+            DeviceType::INPUT
+        };
+        let ports = unsafe { slice::from_raw_parts(ports, n_ports as usize) };
+        for &port in ports {
+            let port_info = unsafe { &*port };
+            let port_name = unsafe { CStr::from_ptr(port_info.name) };
+            let port_description = super::try_cstr_from(port_info.description).unwrap_or(port_name);
+
+            let mut friendly_name = port_description.to_bytes().to_vec();
+            friendly_name.extend_from_slice(b" (");
+            friendly_name.extend_from_slice(names.friendly_name.to_bytes());
+            friendly_name.push(b')');
+
+            let device_id = ctx.device_cache.borrow_mut().intern_id(
+                devtype,
+                index,
+                &super::device_port_id(name, port_name),
+            );
+            let port_names = DeviceNames {
+                friendly_name: CString::new(friendly_name).expect("description contains no NUL"),
+                ..names.clone()
+            };
+            let port_devinfo = ffi::cubeb_device_info {
+                device_id,
+                devid: device_id as ffi::cubeb_devid,
+                state: ctx.state_from_port(port),
+                preferred: if port == active_port {
+                    devinfo.preferred
+                } else {
+                    ffi::CUBEB_DEVICE_PREF_NONE
+                },
+                ..devinfo
+            };
+            self.devinfo.push(port_devinfo, port_names);
+        }
+
+        // The per-port entries have their own IDs.
+        ctx.device_cache.borrow_mut().release_id(devinfo.device_id);
+    }
+}
+
+struct CardQuery<'a> {
+    context: &'a PulseContext,
+    card: u32,
+    previous_profile: Option<CString>,
+    input_profile: Option<CString>,
+}
+
+struct CardSourceQuery<'a> {
+    context: &'a PulseContext,
+    card: u32,
+    source: Option<CString>,
+}
+
+struct PortQuery<'a> {
+    context: &'a PulseContext,
+    ports: Option<(Vec<CString>, Option<CString>)>,
+}
+
+impl PortQuery<'_> {
+    fn found(
+        &mut self,
+        ports: *mut *mut pa_port_info,
+        n_ports: u32,
+        active_port: *mut pa_port_info,
+    ) {
+        let name = |port: *mut pa_port_info| unsafe { CStr::from_ptr((*port).name) }.to_owned();
+        let ports = if ports.is_null() {
+            Vec::new()
+        } else {
+            unsafe { slice::from_raw_parts(ports, n_ports as usize) }
+                .iter()
+                .map(|&port| name(port))
+                .collect()
+        };
+        let active_port = if active_port.is_null() {
+            None
+        } else {
+            Some(name(active_port))
+        };
+        self.ports = Some((ports, active_port));
+    }
+}
+
+fn port_success(_: &pulse::Context, success: i32, u: *mut c_void) {
+    let ctx = unsafe { &*(u as *mut PulseContext) };
+    if success != 1 {
+        cubeb_log!("Failed to set port");
+    }
+    ctx.mainloop.signal();
+}
+
+fn profile_success(_: &pulse::Context, success: i32, u: *mut c_void) {
+    let ctx = unsafe { &*(u as *mut PulseContext) };
+    if success != 1 {
+        cubeb_log!("Failed to set card profile");
+    }
+    ctx.mainloop.signal();
+}
+
+// Key grouping a sink with its monitor source: the path of the hardware, or
+// the sink's name for sinks without one.
+fn sink_group_id(info: &pulse::SinkInfo) -> CString {
+    match info.proplist().gets("sysfs.path") {
+        Some(p) => p.to_owned(),
+        None => unsafe { CStr::from_ptr(info.name) }.to_owned(),
     }
 }
 
-fn pulse_format_to_cubeb_format(format: pa_sample_format_t) -> ffi::cubeb_device_fmt {
+fn is_monitor_source(info: &pulse::SourceInfo) -> bool {
+    info.monitor_of_sink != PA_INVALID_INDEX
+        || info
+            .proplist()
+            .gets("device.class")
+            .is_some_and(|c| c.to_bytes() == b"monitor")
+}
+
+fn pulse_format_to_cubeb_format(format: pa_sample_format_t) -> Option<ffi::cubeb_device_fmt> {
     match format {
-        PA_SAMPLE_S16LE => ffi::CUBEB_DEVICE_FMT_S16LE,
-        PA_SAMPLE_S16BE => ffi::CUBEB_DEVICE_FMT_S16BE,
-        PA_SAMPLE_FLOAT32LE => ffi::CUBEB_DEVICE_FMT_F32LE,
-        PA_SAMPLE_FLOAT32BE => ffi::CUBEB_DEVICE_FMT_F32BE,
-        // Unsupported format, return F32NE
-        _ => ffi::CUBEB_DEVICE_FMT_F32NE,
+        PA_SAMPLE_S16LE => Some(ffi::CUBEB_DEVICE_FMT_S16LE),
+        PA_SAMPLE_S16BE => Some(ffi::CUBEB_DEVICE_FMT_S16BE),
+        PA_SAMPLE_FLOAT32LE => Some(ffi::CUBEB_DEVICE_FMT_F32LE),
+        PA_SAMPLE_FLOAT32BE => Some(ffi::CUBEB_DEVICE_FMT_F32BE),
+        _ => None,
+    }
+}
+
+// Supported and default cubeb formats of a device running in `format`. When
+// cubeb has no matching format, the server converts whatever we use, so all
+// formats are reported and the default is the one losing the least precision.
+fn device_formats(format: pa_sample_format_t) -> (ffi::cubeb_device_fmt, ffi::cubeb_device_fmt) {
+    if let Some(f) = pulse_format_to_cubeb_format(format) {
+        return (f, f);
+    }
+
+    let default_format = match format {
+        PA_SAMPLE_S32LE | PA_SAMPLE_S24LE | PA_SAMPLE_S24_32LE => ffi::CUBEB_DEVICE_FMT_F32LE,
+        PA_SAMPLE_S32BE | PA_SAMPLE_S24BE | PA_SAMPLE_S24_32BE => ffi::CUBEB_DEVICE_FMT_F32BE,
+        _ => ffi::CUBEB_DEVICE_FMT_S16NE,
+    };
+    (ffi::CUBEB_DEVICE_FMT_ALL, default_format)
+}
+
+// Rate a device runs at without resampling: its current rate, else the
+// server's default rate. The server's alternate rate, which devices may also
+// run at, isn't available to clients, so it can't be reported.
+fn device_default_rate(device_rate: u32, server_rate: u32) -> u32 {
+    if device_rate > 0 {
+        device_rate
+    } else {
+        server_rate
+    }
+}
+
+// Latency range of a sink or source in frames, from the latencies it reports.
+// Devices with fixed latency run at their configured latency. Devices with
+// dynamic latency are configured to the lowest latency their clients asked
+// for within their range, so it's known to be reachable, and run up to the
+// larger of it and `floor`. Their current latency says nothing about how low
+// they can go. Without clients they report no configured latency, and only
+// `floor` can be assumed.
+fn device_latency_frames(
+    latency: pa_usec_t,
+    configured_latency: pa_usec_t,
+    has_latency: bool,
+    dynamic_latency: bool,
+    floor: pa_usec_t,
+    rate: u32,
+) -> (u32, u32) {
+    let to_frames = |usec: pa_usec_t| (usec * pa_usec_t::from(rate) / PA_USEC_PER_SEC) as u32;
+
+    if !has_latency {
+        // The device can't be queried for its latency.
+        return (0, to_frames(configured_latency));
+    }
+
+    if dynamic_latency {
+        if configured_latency > 0 {
+            return (
+                to_frames(configured_latency),
+                to_frames(configured_latency.max(floor)),
+            );
+        }
+        return (to_frames(floor), to_frames(floor));
+    }
+
+    let fixed = if configured_latency > 0 {
+        configured_latency
+    } else {
+        latency
+    };
+    (to_frames(fixed), to_frames(fixed))
+}
+
+// Events to subscribe to for keeping track of devices of `devtype`: sink
+// or source events, and server events for the defaults. None without any,
+// unsubscribing fully.
+fn device_events_mask(devtype: DeviceType) -> pulse::SubscriptionMask {
+    let mut mask = pulse::SubscriptionMask::empty();
+    if devtype.contains(DeviceType::OUTPUT) {
+        mask |= pulse::SubscriptionMask::SINK;
+    }
+    if devtype.contains(DeviceType::INPUT) {
+        mask |= pulse::SubscriptionMask::SOURCE;
+    }
+    if !mask.is_empty() {
+        mask |= pulse::SubscriptionMask::SERVER;
+    }
+    mask
+}
+
+// Lowest latency the default sink can run at, in frames at `rate`, or
+// `fallback` microseconds if the sink doesn't tell.
+fn min_latency_frames(info: Option<&DefaultInfo>, fallback: pa_usec_t, rate: u32) -> u32 {
+    let lo = info.map_or(0, |info| {
+        device_latency_frames(
+            info.latency,
+            info.configured_latency,
+            info.flags.contains(pulse::SinkFlags::LATENCY),
+            info.flags.contains(pulse::SinkFlags::DYNAMIC_LATENCY),
+            fallback,
+            rate,
+        )
+        .0
+    });
+    if lo > 0 {
+        lo
+    } else {
+        (fallback * u64::from(rate) / PA_USEC_PER_SEC) as u32
+    }
+}
+
+// Properties identifying the application to the server.
+fn application_properties(
+    name: Option<&CStr>,
+    options: &ConnectOptions,
+) -> Vec<(&'static str, CString)> {
+    let mut properties = Vec::new();
+    let mut add = |key, value: Option<&CStr>| {
+        if let Some(value) = value {
+            properties.push((key, value.to_owned()));
+        }
+    };
+    add(PA_PROP_APPLICATION_NAME, name);
+    add(PA_PROP_APPLICATION_ID, options.application_id.as_deref());
+    add(
+        PA_PROP_APPLICATION_ICON_NAME,
+        options.application_icon_name.as_deref(),
+    );
+    add(
+        PA_PROP_APPLICATION_VERSION,
+        options.application_version.as_deref(),
+    );
+
+    properties.push((
+        PA_PROP_APPLICATION_PROCESS_ID,
+        CString::new(std::process::id().to_string()).unwrap(),
+    ));
+    let binary = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .and_then(|n| CString::new(n).ok());
+    if let Some(binary) = binary {
+        properties.push((PA_PROP_APPLICATION_PROCESS_BINARY, binary));
+    }
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        application_properties, device_default_rate, device_events_mask, device_formats,
+        device_latency_frames, min_latency_frames, ConnectOptions, DefaultInfo,
+    };
+    use cubeb_backend::{ffi, DeviceType};
+    use pulse_ffi::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn latency_range() {
+        let floor = 10 * PA_USEC_PER_MSEC;
+        // dynamic: from the configured latency, whatever the current one
+        assert_eq!(
+            device_latency_frames(4_000, 40_000, true, true, floor, 48000),
+            (1920, 1920)
+        );
+        assert_eq!(
+            device_latency_frames(500_000, 5_000, true, true, floor, 48000),
+            (240, 480)
+        );
+        assert_eq!(
+            device_latency_frames(25_000, 0, true, true, floor, 44100),
+            (441, 441)
+        );
+        // fixed latency devices can't go below their configured latency
+        assert_eq!(
+            device_latency_frames(10_000, 40_000, true, false, floor, 48000),
+            (1920, 1920)
+        );
+        assert_eq!(
+            device_latency_frames(25_000, 0, true, false, floor, 44100),
+            (1102, 1102)
+        );
+        assert_eq!(
+            device_latency_frames(0, 0, false, false, floor, 48000),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn min_latency() {
+        let info = |latency, configured_latency, flags| DefaultInfo {
+            sample_spec: Default::default(),
+            channel_map: Default::default(),
+            flags,
+            latency,
+            configured_latency,
+        };
+        let dynamic = pulse::SinkFlags::LATENCY | pulse::SinkFlags::DYNAMIC_LATENCY;
+        let fallback = 25 * PA_USEC_PER_MSEC;
+
+        // Dynamic latency sinks go as low as they're configured, whatever
+        // their current latency.
+        assert_eq!(
+            min_latency_frames(Some(&info(4_000, 5_000, dynamic)), fallback, 48000),
+            240
+        );
+        assert_eq!(
+            min_latency_frames(Some(&info(500_000, 40_000, dynamic)), fallback, 48000),
+            1920
+        );
+        // Without clients, only the fallback is known to be reachable.
+        assert_eq!(
+            min_latency_frames(Some(&info(500_000, 0, dynamic)), fallback, 48000),
+            1200
+        );
+        // A device stuck at a high latency.
+        assert_eq!(
+            min_latency_frames(
+                Some(&info(10_000, 60_000, pulse::SinkFlags::LATENCY)),
+                fallback,
+                48000
+            ),
+            2880
+        );
+        // Nothing known: the fallback.
+        assert_eq!(min_latency_frames(None, fallback, 48000), 1200);
+        assert_eq!(
+            min_latency_frames(
+                Some(&info(0, 0, pulse::SinkFlags::empty())),
+                fallback,
+                48000
+            ),
+            1200
+        );
+        assert_eq!(min_latency_frames(None, 10 * PA_USEC_PER_MSEC, 44100), 441);
+    }
+
+    #[test]
+    fn events_mask() {
+        use pulse::SubscriptionMask;
+
+        // Fully unsubscribed once nothing needs events.
+        assert!(device_events_mask(DeviceType::empty()).is_empty());
+        assert_eq!(
+            device_events_mask(DeviceType::OUTPUT).bits(),
+            (SubscriptionMask::SINK | SubscriptionMask::SERVER).bits()
+        );
+        assert_eq!(
+            device_events_mask(DeviceType::INPUT | DeviceType::OUTPUT).bits(),
+            (SubscriptionMask::SINK | SubscriptionMask::SOURCE | SubscriptionMask::SERVER).bits()
+        );
+    }
+
+    #[test]
+    fn application_identity() {
+        let options = ConnectOptions {
+            application_id: Some(CString::new("org.example.Player").unwrap()),
+            application_icon_name: Some(CString::new("example-player").unwrap()),
+            ..Default::default()
+        };
+        let name = CString::new("Example Player").unwrap();
+        let properties = application_properties(Some(&name), &options);
+        let get = |key| {
+            properties
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_str().unwrap())
+        };
+        assert_eq!(get(PA_PROP_APPLICATION_NAME), Some("Example Player"));
+        assert_eq!(get(PA_PROP_APPLICATION_ID), Some("org.example.Player"));
+        assert_eq!(get(PA_PROP_APPLICATION_ICON_NAME), Some("example-player"));
+        assert_eq!(get(PA_PROP_APPLICATION_VERSION), None);
+        let pid = std::process::id().to_string();
+        assert_eq!(get(PA_PROP_APPLICATION_PROCESS_ID), Some(pid.as_str()));
+        assert!(get(PA_PROP_APPLICATION_PROCESS_BINARY).is_some());
+    }
+
+    #[test]
+    fn native_formats() {
+        assert_eq!(
+            device_formats(PA_SAMPLE_S16LE),
+            (ffi::CUBEB_DEVICE_FMT_S16LE, ffi::CUBEB_DEVICE_FMT_S16LE)
+        );
+        assert_eq!(
+            device_formats(PA_SAMPLE_FLOAT32BE),
+            (ffi::CUBEB_DEVICE_FMT_F32BE, ffi::CUBEB_DEVICE_FMT_F32BE)
+        );
+        assert_eq!(
+            device_formats(PA_SAMPLE_S24_32LE),
+            (ffi::CUBEB_DEVICE_FMT_ALL, ffi::CUBEB_DEVICE_FMT_F32LE)
+        );
+        assert_eq!(
+            device_formats(PA_SAMPLE_U8),
+            (ffi::CUBEB_DEVICE_FMT_ALL, ffi::CUBEB_DEVICE_FMT_S16NE)
+        );
+    }
+
+    #[test]
+    fn native_rates() {
+        assert_eq!(device_default_rate(48000, 44100), 48000);
+        assert_eq!(device_default_rate(96000, 0), 96000);
+        assert_eq!(device_default_rate(0, 44100), 44100);
     }
 }
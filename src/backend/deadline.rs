@@ -0,0 +1,96 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use pulse;
+use pulse_ffi::{pa_time_event, pa_usec_t};
+use std::cell::Cell;
+use std::os::raw::c_void;
+use std::ptr;
+use std::time::Duration;
+
+struct State {
+    mainloop: *const pulse::ThreadedMainloop,
+    expired: Cell<bool>,
+}
+
+/// A timer on the mainloop bounding a wait on it: once `timeout` has passed,
+/// the waiter is woken up and `expired` returns true.
+///
+/// Must be created and dropped with the mainloop locked. `mainloop` must
+/// outlive the deadline.
+pub struct Deadline {
+    state: Box<State>,
+    event: *mut pa_time_event,
+}
+
+impl Deadline {
+    /// A deadline `timeout` from now, or one that never expires for `None`.
+    pub fn new(
+        mainloop: &pulse::ThreadedMainloop,
+        context: Option<&pulse::Context>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        fn expire(
+            _: &pulse::MainloopApi,
+            _: *mut pa_time_event,
+            _: &pulse::TimeVal,
+            u: *mut c_void,
+        ) {
+            let state = unsafe { &*(u as *const State) };
+            state.expired.set(true);
+            unsafe { &*state.mainloop }.signal();
+        }
+
+        let state = Box::new(State {
+            mainloop,
+            expired: Cell::new(false),
+        });
+        let event = match (context, timeout) {
+            (Some(context), Some(timeout)) => {
+                let usec = pulse::rtclock_now().saturating_add(usec(timeout));
+                let event = context.rttime_new(usec, expire, &*state as *const _ as *mut _);
+                if event.is_null() {
+                    cubeb_log!("Error: couldn't set up a timeout, waiting without one");
+                }
+                event
+            }
+            _ => ptr::null_mut(),
+        };
+
+        Deadline { state, event }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.state.expired.get()
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        if !self.event.is_null() {
+            unsafe { &*self.state.mainloop }
+                .get_api()
+                .time_free(self.event);
+        }
+    }
+}
+
+/// `timeout` in microseconds, saturating.
+pub fn usec(timeout: Duration) -> pa_usec_t {
+    timeout.as_micros().min(u128::from(pa_usec_t::MAX)) as pa_usec_t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::usec;
+    use std::time::Duration;
+
+    #[test]
+    fn timeout_usec() {
+        assert_eq!(usec(Duration::from_millis(1500)), 1_500_000);
+        assert_eq!(usec(Duration::from_nanos(999)), 0);
+        assert_eq!(usec(Duration::MAX), u64::MAX);
+    }
+}
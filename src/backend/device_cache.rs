@@ -0,0 +1,685 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use backend::intern::Intern;
+use backend::try_cstr_from;
+use cubeb_backend::{ffi, DeviceType};
+use pulse_ffi::pa_port_info;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::{mem, ptr, slice};
+
+/// The properties of a sink or source that show up in enumeration, used to
+/// tell whether a `Change` event is worth a device-collection-changed
+/// notification.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeviceSnapshot {
+    description: CString,
+    active_port: Option<CString>,
+    // (name, availability) of every port
+    ports: Vec<(CString, c_int)>,
+}
+
+impl DeviceSnapshot {
+    pub fn new(
+        description: *const c_char,
+        ports: *mut *mut pa_port_info,
+        n_ports: u32,
+        active_port: *mut pa_port_info,
+    ) -> Self {
+        let port_name = |p: &pa_port_info| try_cstr_from(p.name).map(CStr::to_owned);
+        let ports = if ports.is_null() {
+            &[][..]
+        } else {
+            unsafe { slice::from_raw_parts(ports, n_ports as usize) }
+        };
+        DeviceSnapshot {
+            description: try_cstr_from(description)
+                .map(CStr::to_owned)
+                .unwrap_or_default(),
+            active_port: unsafe { active_port.as_ref() }.and_then(port_name),
+            ports: ports
+                .iter()
+                .filter_map(|&p| unsafe { p.as_ref() })
+                .map(|p| (port_name(p).unwrap_or_default(), p.available))
+                .collect(),
+        }
+    }
+}
+
+/// The strings of a device, owned by the `DeviceInfos` it's added to.
+#[derive(Clone, Debug)]
+pub struct DeviceNames {
+    pub friendly_name: CString,
+    pub group_id: Option<CString>,
+    pub vendor_name: Option<CString>,
+}
+
+/// A list of enumerated devices, owning their strings. `device_id` isn't
+/// owned: the list holds a reference on it in the cache's `Intern`, which
+/// `DeviceCache::release_devices` drops.
+///
+/// This is the only place where the strings and the array handed out in a
+/// `cubeb_device_collection` are allocated and freed: the string fields all
+/// come from `CString::into_raw`, and the array from a boxed slice.
+#[derive(Debug, Default)]
+pub struct DeviceInfos(Vec<ffi::cubeb_device_info>);
+
+impl DeviceInfos {
+    pub fn new() -> Self {
+        DeviceInfos::default()
+    }
+
+    /// Adds `device`, whose string fields are replaced with `names`.
+    pub fn push(&mut self, device: ffi::cubeb_device_info, names: DeviceNames) {
+        fn into_raw(s: Option<CString>) -> *const c_char {
+            s.map_or(ptr::null(), |s| s.into_raw())
+        }
+
+        self.0.push(ffi::cubeb_device_info {
+            friendly_name: names.friendly_name.into_raw(),
+            group_id: into_raw(names.group_id),
+            vendor_name: into_raw(names.vendor_name),
+            ..device
+        });
+    }
+
+    /// Moves the devices of `other` to the end of the list.
+    pub fn append(&mut self, other: &mut DeviceInfos) {
+        self.0.append(&mut other.0);
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, ffi::cubeb_device_info> {
+        self.0.iter()
+    }
+
+    /// Copies the devices and their strings.
+    pub fn duplicate(&self) -> DeviceInfos {
+        fn dup(s: *const c_char) -> Option<CString> {
+            try_cstr_from(s).map(CStr::to_owned)
+        }
+
+        let mut copy = DeviceInfos(Vec::with_capacity(self.0.len()));
+        for d in &self.0 {
+            let names = DeviceNames {
+                friendly_name: dup(d.friendly_name).unwrap_or_default(),
+                group_id: dup(d.group_id),
+                vendor_name: dup(d.vendor_name),
+            };
+            copy.push(ffi::cubeb_device_info { ..*d }, names);
+        }
+        copy
+    }
+
+    /// Hands the devices over to `collection`, until `from_collection` takes
+    /// them back.
+    pub fn into_collection(mut self, collection: &mut ffi::cubeb_device_collection) {
+        let devices = mem::take(&mut self.0).into_boxed_slice();
+        collection.count = devices.len();
+        collection.device = Box::into_raw(devices) as *mut ffi::cubeb_device_info;
+    }
+
+    /// Takes back the devices handed over to `collection` by
+    /// `into_collection`, emptying it.
+    pub unsafe fn from_collection(collection: &mut ffi::cubeb_device_collection) -> Self {
+        let device = mem::replace(&mut collection.device, ptr::null_mut());
+        let count = mem::replace(&mut collection.count, 0);
+        if device.is_null() {
+            return DeviceInfos::new();
+        }
+        let devices = Box::from_raw(ptr::slice_from_raw_parts_mut(device, count));
+        DeviceInfos(devices.into_vec())
+    }
+}
+
+impl Drop for DeviceInfos {
+    fn drop(&mut self) {
+        for d in &self.0 {
+            for s in [d.friendly_name, d.group_id, d.vendor_name] {
+                if !s.is_null() {
+                    let _ = unsafe { CString::from_raw(s as *mut _) };
+                }
+            }
+        }
+    }
+}
+
+/// Last known state of the sinks, sources and defaults, kept up to date
+/// from subscription events, and the registry of device IDs.
+#[derive(Debug)]
+pub struct DeviceCache {
+    sinks: HashMap<u32, DeviceSnapshot>,
+    sources: HashMap<u32, DeviceSnapshot>,
+    default_sink: Option<CString>,
+    default_source: Option<CString>,
+    // IDs of the default devices as last reported, with a reference on each.
+    // Kept across `reset` so that a change over a reconnection is reported.
+    default_output_id: *const c_char,
+    default_input_id: *const c_char,
+    // The types of devices that subscription events keep up to date;
+    // enumeration results of other types aren't cached.
+    tracking: DeviceType,
+    // Enumeration results, dropped whenever a device of that direction or
+    // the default changes.
+    outputs: Option<DeviceInfos>,
+    inputs: Option<DeviceInfos>,
+    // Device IDs, referenced by the enumeration results above, by device
+    // collections handed out and by streams. Kept across `reset` so that
+    // IDs stay stable over a reconnection.
+    ids: Intern,
+    // The IDs of each sink and source on the server, by index, with a
+    // reference on each until the device is removed: apps keep IDs from
+    // enumeration after destroying the collection, to open streams later.
+    sink_ids: HashMap<u32, Vec<*const c_char>>,
+    source_ids: HashMap<u32, Vec<*const c_char>>,
+    // IDs of the devices known before a reconnection, referenced until the
+    // device is seen again or an enumeration of its type completes without
+    // it.
+    orphan_ids: Vec<(DeviceType, *const c_char)>,
+}
+
+impl Default for DeviceCache {
+    fn default() -> Self {
+        DeviceCache {
+            sinks: HashMap::new(),
+            sources: HashMap::new(),
+            default_sink: None,
+            default_source: None,
+            default_output_id: ptr::null(),
+            default_input_id: ptr::null(),
+            tracking: DeviceType::empty(),
+            outputs: None,
+            inputs: None,
+            ids: Intern::new(),
+            sink_ids: HashMap::new(),
+            source_ids: HashMap::new(),
+            orphan_ids: Vec::new(),
+        }
+    }
+}
+
+impl DeviceCache {
+    /// Forgets everything learnt from the server, keeping the device IDs
+    /// still referenced elsewhere, or whose devices may still exist.
+    pub fn reset(&mut self) {
+        self.set_tracking(DeviceType::empty());
+        self.sinks.clear();
+        self.sources.clear();
+        for (_, ids) in self.sink_ids.drain() {
+            self.orphan_ids
+                .extend(ids.into_iter().map(|id| (DeviceType::OUTPUT, id)));
+        }
+        for (_, ids) in self.source_ids.drain() {
+            self.orphan_ids
+                .extend(ids.into_iter().map(|id| (DeviceType::INPUT, id)));
+        }
+        self.default_sink = None;
+        self.default_source = None;
+    }
+
+    fn devices(&mut self, devtype: DeviceType) -> &mut HashMap<u32, DeviceSnapshot> {
+        if devtype.contains(DeviceType::OUTPUT) {
+            &mut self.sinks
+        } else {
+            &mut self.sources
+        }
+    }
+
+    /// Records `snapshot` for the device, returning whether it differs from
+    /// what was previously known about it.
+    pub fn update(&mut self, devtype: DeviceType, index: u32, snapshot: DeviceSnapshot) -> bool {
+        match self.devices(devtype).insert(index, snapshot) {
+            Some(previous) => previous != self.devices(devtype)[&index],
+            None => false,
+        }
+    }
+
+    /// Forgets the device, releasing its IDs once nothing else references
+    /// them.
+    pub fn remove(&mut self, devtype: DeviceType, index: u32) {
+        self.devices(devtype).remove(&index);
+        if let Some(ids) = self.device_ids(devtype).remove(&index) {
+            for id in ids {
+                self.ids.release(id);
+            }
+        }
+    }
+
+    fn device_ids(&mut self, devtype: DeviceType) -> &mut HashMap<u32, Vec<*const c_char>> {
+        if devtype.contains(DeviceType::OUTPUT) {
+            &mut self.sink_ids
+        } else {
+            &mut self.source_ids
+        }
+    }
+
+    /// Returns the ID `name` of the device at `index`, taking a reference on
+    /// it. The ID stays valid until the device is removed.
+    pub fn intern_id(&mut self, devtype: DeviceType, index: u32, name: &CStr) -> *const c_char {
+        let id = self.ids.add(name);
+        let known = self
+            .device_ids(devtype)
+            .get(&index)
+            .is_some_and(|ids| ids.contains(&id));
+        if !known {
+            match self
+                .orphan_ids
+                .iter()
+                .position(|&orphan| orphan == (devtype, id))
+            {
+                Some(i) => {
+                    self.orphan_ids.swap_remove(i);
+                }
+                None => {
+                    self.ids.retain(id);
+                }
+            }
+            self.device_ids(devtype).entry(index).or_default().push(id);
+        }
+        id
+    }
+
+    /// Drops the references on the IDs of devices of the given types known
+    /// before a reconnection and not seen since.
+    pub fn release_orphans(&mut self, devtype: DeviceType) {
+        let ids = &mut self.ids;
+        self.orphan_ids.retain(|&(t, id)| {
+            if devtype.contains(t) {
+                ids.release(id);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Takes a reference on `id` if it's a device ID handed out by this
+    /// cache.
+    pub fn retain_id(&mut self, id: *const c_char) -> bool {
+        self.ids.retain(id)
+    }
+
+    pub fn release_id(&mut self, id: *const c_char) {
+        self.ids.release(id);
+    }
+
+    /// Drops `devices` along with their references on device IDs.
+    pub fn release_devices(&mut self, devices: DeviceInfos) {
+        for d in &devices.0 {
+            self.ids.release(d.device_id);
+        }
+    }
+
+    fn infos(&mut self, devtype: DeviceType) -> &mut Option<DeviceInfos> {
+        if devtype.contains(DeviceType::OUTPUT) {
+            &mut self.outputs
+        } else {
+            &mut self.inputs
+        }
+    }
+
+    pub fn set_tracking(&mut self, tracking: DeviceType) {
+        self.tracking = tracking;
+        for t in [DeviceType::OUTPUT, DeviceType::INPUT] {
+            if !tracking.contains(t) {
+                self.invalidate(t);
+                // Would go stale without events.
+                self.devices(t).clear();
+            }
+        }
+    }
+
+    pub fn is_tracking(&self, devtype: DeviceType) -> bool {
+        self.tracking.contains(devtype)
+    }
+
+    /// Copies the cached enumeration results, with a reference on each
+    /// device ID for the copies.
+    pub fn enumerated(&mut self, devtype: DeviceType) -> Option<DeviceInfos> {
+        let devices = self.infos(devtype).take()?;
+        let copies = self.copy_devices(&devices);
+        *self.infos(devtype) = Some(devices);
+        Some(copies)
+    }
+
+    /// Copies `devices`, with a reference on each device ID for the copies.
+    pub fn copy_devices(&mut self, devices: &DeviceInfos) -> DeviceInfos {
+        let copies = devices.duplicate();
+        for d in copies.iter() {
+            let retained = self.ids.retain(d.device_id);
+            debug_assert!(retained);
+        }
+        copies
+    }
+
+    /// Records the results of a completed enumeration, which `intern_id`
+    /// was called for every device of.
+    pub fn set_enumerated(&mut self, devtype: DeviceType, devices: DeviceInfos) {
+        // Whatever wasn't seen is gone.
+        self.release_orphans(devtype);
+        if !self.tracking.contains(devtype) {
+            self.release_devices(devices);
+            return;
+        }
+        if let Some(previous) = self.infos(devtype).replace(devices) {
+            self.release_devices(previous);
+        }
+    }
+
+    /// Drops the enumeration results of the given types.
+    pub fn invalidate(&mut self, devtype: DeviceType) {
+        for t in [DeviceType::OUTPUT, DeviceType::INPUT] {
+            if devtype.contains(t) {
+                if let Some(previous) = self.infos(t).take() {
+                    self.release_devices(previous);
+                }
+            }
+        }
+    }
+
+    /// Records `id` as the ID of the default device of `devtype`. If that's
+    /// a change from a previously recorded one, returns the previous ID and
+    /// the new one, the caller taking over the reference on the previous.
+    pub fn update_default_id(
+        &mut self,
+        devtype: DeviceType,
+        id: &CStr,
+    ) -> Option<(*const c_char, *const c_char)> {
+        let id = self.ids.add(id);
+        let current = if devtype.contains(DeviceType::OUTPUT) {
+            &mut self.default_output_id
+        } else {
+            &mut self.default_input_id
+        };
+        if *current == id {
+            self.ids.release(id);
+            return None;
+        }
+        let previous = mem::replace(current, id);
+        if previous.is_null() {
+            None
+        } else {
+            Some((previous, id))
+        }
+    }
+
+    /// Whether `name` is the default device of `devtype`, as last recorded
+    /// by `update_defaults`.
+    pub fn is_default(&self, devtype: DeviceType, name: &CStr) -> bool {
+        let default = if devtype.contains(DeviceType::OUTPUT) {
+            &self.default_sink
+        } else {
+            &self.default_source
+        };
+        default.as_deref() == Some(name)
+    }
+
+    /// Records the default sink and source, returning the types whose
+    /// default changed from a previously known one.
+    pub fn update_defaults(&mut self, sink: Option<&CStr>, source: Option<&CStr>) -> DeviceType {
+        fn update(cached: &mut Option<CString>, name: Option<&CStr>) -> bool {
+            let name = name.map(CStr::to_owned);
+            let changed = cached.is_some() && *cached != name;
+            *cached = name;
+            changed
+        }
+
+        let mut changed = DeviceType::empty();
+        if update(&mut self.default_sink, sink) {
+            changed |= DeviceType::OUTPUT;
+        }
+        if update(&mut self.default_source, source) {
+            changed |= DeviceType::INPUT;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceCache, DeviceInfos, DeviceNames, DeviceSnapshot};
+    use backend::try_cstr_from;
+    use cubeb_backend::{ffi, DeviceType};
+    use pulse_ffi::{pa_port_info, PA_PORT_AVAILABLE_NO, PA_PORT_AVAILABLE_YES};
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    fn cstr(s: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(s).unwrap()
+    }
+
+    fn device(device_id: *const c_char) -> ffi::cubeb_device_info {
+        ffi::cubeb_device_info {
+            device_id,
+            ..unsafe { ::std::mem::zeroed() }
+        }
+    }
+
+    fn names(friendly_name: &str) -> DeviceNames {
+        DeviceNames {
+            friendly_name: CString::new(friendly_name).unwrap(),
+            group_id: None,
+            vendor_name: None,
+        }
+    }
+
+    #[test]
+    fn port_availability_change() {
+        let mut speakers = pa_port_info {
+            name: cstr(b"speakers\0").as_ptr(),
+            available: PA_PORT_AVAILABLE_YES,
+            ..Default::default()
+        };
+        let mut headphones = pa_port_info {
+            name: cstr(b"headphones\0").as_ptr(),
+            available: PA_PORT_AVAILABLE_NO,
+            ..Default::default()
+        };
+        let description = cstr(b"Built-in Audio\0").as_ptr();
+        let mut ports = [&mut speakers as *mut _, &mut headphones as *mut _];
+
+        let mut cache = DeviceCache::default();
+        let snapshot = DeviceSnapshot::new(description, ports.as_mut_ptr(), 2, ports[0]);
+        assert!(!cache.update(DeviceType::OUTPUT, 1, snapshot));
+        let snapshot = DeviceSnapshot::new(description, ports.as_mut_ptr(), 2, ports[0]);
+        assert!(!cache.update(DeviceType::OUTPUT, 1, snapshot));
+
+        unsafe { (*ports[1]).available = PA_PORT_AVAILABLE_YES };
+        let snapshot = DeviceSnapshot::new(description, ports.as_mut_ptr(), 2, ports[0]);
+        assert!(cache.update(DeviceType::OUTPUT, 1, snapshot));
+
+        // Same index, other direction.
+        let snapshot = DeviceSnapshot::new(description, ptr::null_mut(), 0, ptr::null_mut());
+        assert!(!cache.update(DeviceType::INPUT, 1, snapshot));
+    }
+
+    #[test]
+    fn enumeration_invalidated() {
+        let mut cache = DeviceCache::default();
+        cache.set_tracking(DeviceType::OUTPUT);
+        let device_id = cache.intern_id(DeviceType::OUTPUT, 1, cstr(b"sink\0"));
+        let mut devices = DeviceInfos::new();
+        devices.push(device(device_id), names("Speakers"));
+        let friendly_name = devices.iter().next().unwrap().friendly_name;
+        cache.set_enumerated(DeviceType::OUTPUT, devices);
+
+        let copy = cache.enumerated(DeviceType::OUTPUT).unwrap();
+        assert_eq!(copy.iter().count(), 1);
+        let d = copy.iter().next().unwrap();
+        assert_eq!(d.device_id, device_id);
+        assert_ne!(d.friendly_name, friendly_name);
+        assert_eq!(
+            try_cstr_from(d.friendly_name).unwrap().to_bytes(),
+            b"Speakers"
+        );
+
+        assert!(cache.enumerated(DeviceType::INPUT).is_none());
+        cache.invalidate(DeviceType::INPUT);
+        assert!(cache.enumerated(DeviceType::OUTPUT).is_some());
+        cache.invalidate(DeviceType::all());
+        assert!(cache.enumerated(DeviceType::OUTPUT).is_none());
+
+        // The copy's reference keeps the ID alive past the cached list and a
+        // reset.
+        cache.reset();
+        assert!(cache.intern_id(DeviceType::OUTPUT, 1, cstr(b"sink\0")) == device_id);
+        cache.release_id(device_id);
+        cache.release_devices(copy);
+    }
+
+    #[test]
+    fn ids_outlive_collections() {
+        let mut cache = DeviceCache::default();
+        let device_id = cache.intern_id(DeviceType::OUTPUT, 4, cstr(b"sink\0"));
+        let mut devices = DeviceInfos::new();
+        devices.push(device(device_id), names("Speakers"));
+        let mut coll = ffi::cubeb_device_collection {
+            device: ptr::null_mut(),
+            count: 0,
+        };
+        devices.into_collection(&mut coll);
+        let devid = unsafe { &*coll.device }.device_id;
+        cache.release_devices(unsafe { DeviceInfos::from_collection(&mut coll) });
+
+        // A stream opened on the device after its collection was destroyed.
+        assert!(cache.retain_id(devid));
+        assert_eq!(try_cstr_from(devid), Some(cstr(b"sink\0")));
+        cache.release_id(devid);
+
+        // Over a reconnection, until the device is seen again.
+        cache.reset();
+        assert!(cache.retain_id(devid));
+        cache.release_id(devid);
+        assert!(cache.intern_id(DeviceType::OUTPUT, 7, cstr(b"sink\0")) == devid);
+        cache.release_id(devid);
+
+        // Reclaimed once the device is removed.
+        cache.remove(DeviceType::INPUT, 7);
+        assert!(cache.retain_id(devid));
+        cache.release_id(devid);
+        cache.remove(DeviceType::OUTPUT, 7);
+        assert!(!cache.retain_id(devid));
+    }
+
+    #[test]
+    fn orphans_released() {
+        let mut cache = DeviceCache::default();
+        let kept = cache.intern_id(DeviceType::OUTPUT, 1, cstr(b"kept "));
+        let gone = cache.intern_id(DeviceType::OUTPUT, 2, cstr(b"gone "));
+        let source = cache.intern_id(DeviceType::INPUT, 2, cstr(b"gone "));
+        assert!(source == gone);
+        for id in [kept, gone, source] {
+            cache.release_id(id);
+        }
+
+        cache.reset();
+        assert!(cache.intern_id(DeviceType::OUTPUT, 5, cstr(b"kept ")) == kept);
+        cache.release_id(kept);
+        cache.set_enumerated(DeviceType::OUTPUT, DeviceInfos::new());
+        assert!(cache.retain_id(kept));
+        cache.release_id(kept);
+        // Still pinned by the source of the same name, not enumerated yet.
+        assert!(cache.retain_id(gone));
+        cache.release_id(gone);
+
+        cache.release_orphans(DeviceType::all());
+        assert!(!cache.retain_id(gone));
+    }
+
+    #[test]
+    fn collection_round_trip() {
+        let mut devices = DeviceInfos::new();
+        devices.push(device(ptr::null()), names("Speakers"));
+        let mut more = DeviceInfos::new();
+        more.push(device(ptr::null()), names("Headphones"));
+        devices.append(&mut more);
+        assert_eq!(more.iter().count(), 0);
+
+        let mut coll = ffi::cubeb_device_collection {
+            device: ptr::null_mut(),
+            count: 0,
+        };
+        devices.into_collection(&mut coll);
+        assert_eq!(coll.count, 2);
+        let devices = unsafe { DeviceInfos::from_collection(&mut coll) };
+        assert!(coll.device.is_null());
+        assert_eq!(coll.count, 0);
+        let names: Vec<_> = devices
+            .iter()
+            .map(|d| try_cstr_from(d.friendly_name).unwrap().to_bytes())
+            .collect();
+        assert_eq!(names, [&b"Speakers"[..], b"Headphones"]);
+        assert!(devices.iter().all(|d| d.vendor_name.is_null()));
+
+        // Taking back an empty collection yields no devices.
+        let devices = unsafe { DeviceInfos::from_collection(&mut coll) };
+        assert_eq!(devices.iter().count(), 0);
+    }
+
+    #[test]
+    fn untracked_not_cached() {
+        let mut cache = DeviceCache::default();
+        cache.set_tracking(DeviceType::INPUT);
+        let devices = DeviceInfos::new();
+        cache.set_enumerated(DeviceType::OUTPUT, devices);
+        assert!(cache.enumerated(DeviceType::OUTPUT).is_none());
+
+        let devices = DeviceInfos::new();
+        cache.set_enumerated(DeviceType::INPUT, devices);
+        assert!(cache.enumerated(DeviceType::INPUT).is_some());
+
+        // Snapshots of devices no longer tracked are dropped.
+        let description = cstr(b"Mic\0").as_ptr();
+        let snapshot = DeviceSnapshot::new(description, ptr::null_mut(), 0, ptr::null_mut());
+        assert!(!cache.update(DeviceType::INPUT, 2, snapshot));
+        cache.set_tracking(DeviceType::empty());
+        assert!(cache.enumerated(DeviceType::INPUT).is_none());
+        let description = cstr(b"Line In\0").as_ptr();
+        let snapshot = DeviceSnapshot::new(description, ptr::null_mut(), 0, ptr::null_mut());
+        assert!(!cache.update(DeviceType::INPUT, 2, snapshot));
+    }
+
+    #[test]
+    fn default_id_change() {
+        let mut cache = DeviceCache::default();
+        assert_eq!(
+            cache.update_default_id(DeviceType::OUTPUT, cstr(b"a\0")),
+            None
+        );
+        assert_eq!(
+            cache.update_default_id(DeviceType::OUTPUT, cstr(b"a\0")),
+            None
+        );
+        assert_eq!(
+            cache.update_default_id(DeviceType::INPUT, cstr(b"b\0")),
+            None
+        );
+
+        let a = cache.intern_id(DeviceType::OUTPUT, 0, cstr(b"a\0"));
+        let (previous, current) = cache
+            .update_default_id(DeviceType::OUTPUT, cstr(b"c\0"))
+            .unwrap();
+        assert!(previous == a);
+        assert_eq!(unsafe { CStr::from_ptr(current) }, cstr(b"c\0"));
+        cache.release_id(previous);
+        // Still referenced by `a`.
+        assert!(cache.retain_id(a));
+    }
+
+    #[test]
+    fn default_change() {
+        let mut cache = DeviceCache::default();
+        let (a, b) = (cstr(b"a\0"), cstr(b"b\0"));
+        assert_eq!(cache.update_defaults(Some(a), Some(a)), DeviceType::empty());
+        assert_eq!(cache.update_defaults(Some(a), Some(a)), DeviceType::empty());
+        assert_eq!(cache.update_defaults(Some(b), Some(a)), DeviceType::OUTPUT);
+        assert!(cache.is_default(DeviceType::OUTPUT, b));
+        assert!(!cache.is_default(DeviceType::OUTPUT, a));
+        assert!(cache.is_default(DeviceType::INPUT, a));
+        assert_eq!(cache.update_defaults(Some(b), None), DeviceType::INPUT);
+    }
+}
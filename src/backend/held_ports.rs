@@ -0,0 +1,156 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use cubeb_backend::DeviceType;
+use std::ffi::{CStr, CString};
+
+/// Sinks and sources with streams opened on one of their ports, which must
+/// stay active until the last of those streams is destroyed, with the port
+/// to restore then if it was switched. The active port is server-wide state
+/// the user chose.
+#[derive(Debug, Default)]
+pub struct HeldPorts {
+    held: Vec<HeldPort>,
+}
+
+#[derive(Debug)]
+struct HeldPort {
+    devtype: DeviceType,
+    device: CString,
+    port: CString,
+    previous: Option<CString>,
+    streams: usize,
+}
+
+/// Outcome of `HeldPorts::retain`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Retain {
+    Held,
+    NotHeld,
+    /// Held on another port, which streams opened on it would be moved away
+    /// from.
+    OtherPort,
+}
+
+impl HeldPorts {
+    pub fn new() -> Self {
+        HeldPorts::default()
+    }
+
+    /// Takes another reference on `device` if it's already held on `port`.
+    pub fn retain(&mut self, devtype: DeviceType, device: &CStr, port: &CStr) -> Retain {
+        match self.find(devtype, device) {
+            Some(i) if self.held[i].port.as_c_str() == port => {
+                self.held[i].streams += 1;
+                Retain::Held
+            }
+            Some(_) => Retain::OtherPort,
+            None => Retain::NotHeld,
+        }
+    }
+
+    /// Records that `device` is held on `port` for one stream, switched away
+    /// from the port `previous` if any.
+    pub fn insert(
+        &mut self,
+        devtype: DeviceType,
+        device: &CStr,
+        port: CString,
+        previous: Option<CString>,
+    ) {
+        debug_assert!(self.find(devtype, device).is_none());
+        self.held.push(HeldPort {
+            devtype,
+            device: device.to_owned(),
+            port,
+            previous,
+            streams: 1,
+        });
+    }
+
+    /// Drops a reference on `device`, returning the port to restore when it
+    /// was the last one and the port was switched.
+    pub fn release(&mut self, devtype: DeviceType, device: &CStr) -> Option<CString> {
+        let i = self.find(devtype, device)?;
+        self.held[i].streams -= 1;
+        if self.held[i].streams == 0 {
+            self.held.swap_remove(i).previous
+        } else {
+            None
+        }
+    }
+
+    fn find(&self, devtype: DeviceType, device: &CStr) -> Option<usize> {
+        self.held
+            .iter()
+            .position(|h| h.devtype == devtype && h.device.as_c_str() == device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeldPorts, Retain};
+    use cubeb_backend::DeviceType;
+    use std::ffi::{CStr, CString};
+
+    fn cstr(s: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(s).unwrap()
+    }
+
+    #[test]
+    fn restore_after_last_stream() {
+        let sink = cstr(b"alsa_output.analog-stereo\0");
+        let headphones = cstr(b"analog-output-headphones\0");
+        let mut held = HeldPorts::new();
+        assert_eq!(
+            held.retain(DeviceType::OUTPUT, sink, headphones),
+            Retain::NotHeld
+        );
+        held.insert(
+            DeviceType::OUTPUT,
+            sink,
+            headphones.to_owned(),
+            Some(CString::new("analog-output-speaker").unwrap()),
+        );
+        // Sources are held separately, even under the same name.
+        assert_eq!(
+            held.retain(DeviceType::INPUT, sink, headphones),
+            Retain::NotHeld
+        );
+        assert_eq!(
+            held.retain(DeviceType::OUTPUT, sink, headphones),
+            Retain::Held
+        );
+        assert_eq!(held.release(DeviceType::OUTPUT, sink), None);
+        assert_eq!(
+            held.release(DeviceType::OUTPUT, sink),
+            Some(CString::new("analog-output-speaker").unwrap())
+        );
+        assert_eq!(held.release(DeviceType::OUTPUT, sink), None);
+        assert_eq!(
+            held.retain(DeviceType::OUTPUT, sink, headphones),
+            Retain::NotHeld
+        );
+    }
+
+    #[test]
+    fn other_port_held() {
+        let sink = cstr(b"alsa_output.analog-stereo\0");
+        let speaker = cstr(b"analog-output-speaker\0");
+        let headphones = cstr(b"analog-output-headphones\0");
+        let mut held = HeldPorts::new();
+        // Already active, nothing to restore, but still held.
+        held.insert(DeviceType::OUTPUT, sink, speaker.to_owned(), None);
+        assert_eq!(
+            held.retain(DeviceType::OUTPUT, sink, headphones),
+            Retain::OtherPort
+        );
+        assert_eq!(held.release(DeviceType::OUTPUT, sink), None);
+        assert_eq!(
+            held.retain(DeviceType::OUTPUT, sink, headphones),
+            Retain::NotHeld
+        );
+    }
+}
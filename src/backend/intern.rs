@@ -3,31 +3,85 @@
 // This program is made available under an ISC-style license.  See the
 // accompanying file LICENSE for details.
 
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 
-#[derive(Debug)]
+/// Reference counted string interning, used for device IDs.
+///
+/// A string handed out by `add` keeps its address for as long as references
+/// on it are held, and is freed with the last one. The heap buffer of a
+/// `CString` doesn't move when the map rehashes.
+#[derive(Debug, Default)]
 pub struct Intern {
-    vec: Vec<CString>,
+    map: HashMap<CString, usize>,
+    // Addresses of the strings in `map`, so that a pointer is only
+    // dereferenced once known to be one of them.
+    ptrs: HashSet<*const c_char>,
 }
 
 impl Intern {
     pub fn new() -> Intern {
-        Intern { vec: Vec::new() }
+        Intern::default()
     }
 
+    /// Returns the interned copy of `string`, taking a reference on it.
     pub fn add(&mut self, string: &CStr) -> *const c_char {
-        fn eq(s1: &CStr, s2: &CStr) -> bool {
-            s1 == s2
+        if let Some((s, _)) = self.map.get_key_value(string) {
+            let ptr = s.as_ptr();
+            *self.map.get_mut(string).unwrap() += 1;
+            return ptr;
         }
-        for s in &self.vec {
-            if eq(s, string) {
-                return s.as_ptr();
+
+        let s = string.to_owned();
+        let ptr = s.as_ptr();
+        self.map.insert(s, 1);
+        self.ptrs.insert(ptr);
+        ptr
+    }
+
+    /// Takes another reference on `ptr` if it's a string returned by `add`
+    /// that is still interned.
+    pub fn retain(&mut self, ptr: *const c_char) -> bool {
+        match self.refs(ptr) {
+            Some(refs) => {
+                *refs += 1;
+                true
             }
+            None => false,
         }
+    }
 
-        self.vec.push(string.to_owned());
-        self.vec.last().unwrap().as_ptr()
+    /// Drops a reference taken by `add` or `retain`.
+    pub fn release(&mut self, ptr: *const c_char) {
+        let last = match self.refs(ptr) {
+            Some(refs) => {
+                *refs -= 1;
+                *refs == 0
+            }
+            None => {
+                debug_assert!(false, "releasing a string that isn't interned");
+                return;
+            }
+        };
+        if last {
+            let key = unsafe { CStr::from_ptr(ptr) }.to_owned();
+            self.map.remove(key.as_c_str());
+            self.ptrs.remove(&ptr);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn refs(&mut self, ptr: *const c_char) -> Option<&mut usize> {
+        if !self.ptrs.contains(&ptr) {
+            return None;
+        }
+        let string = unsafe { CStr::from_ptr(ptr) };
+        self.map.get_mut(string)
     }
 }
 
@@ -36,6 +90,10 @@ mod tests {
     use super::Intern;
     use std::ffi::CStr;
 
+    fn cstr(str: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(str).unwrap()
+    }
+
     #[test]
     fn intern() {
         fn cstr(str: &[u8]) -> &CStr {
@@ -55,4 +113,40 @@ mod tests {
         assert!(foo_ptr != intern.add(cstr(b"fool\0")));
         assert!(foo_ptr != intern.add(cstr(b"not foo\0")));
     }
+
+    #[test]
+    fn reclaim() {
+        let mut intern = Intern::new();
+
+        let foo_ptr = intern.add(cstr(b"foo\0"));
+        assert!(intern.retain(foo_ptr));
+        assert!(intern.add(cstr(b"foo\0")) == foo_ptr);
+
+        // Many short-lived devices don't accumulate.
+        for i in 0..100 {
+            let name = format!("usb-{}\0", i);
+            let ptr = intern.add(cstr(name.as_bytes()));
+            intern.release(ptr);
+        }
+        assert_eq!(intern.len(), 1);
+
+        intern.release(foo_ptr);
+        intern.release(foo_ptr);
+        assert_eq!(intern.len(), 1);
+        intern.release(foo_ptr);
+        assert_eq!(intern.len(), 0);
+        // Not dereferenced once freed.
+        assert!(!intern.retain(foo_ptr));
+    }
+
+    #[test]
+    fn retain_foreign_string() {
+        let mut intern = Intern::new();
+        let foo_ptr = intern.add(cstr(b"foo\0"));
+
+        // Same contents, different address: not one of ours.
+        let other = cstr(b"foo\0").to_owned();
+        assert!(!intern.retain(other.as_ptr()));
+        assert!(intern.retain(foo_ptr));
+    }
 }
@@ -3,15 +3,21 @@
 // This program is made available under an ISC-style license.  See the
 // accompanying file LICENSE for details.
 
+mod card_profile;
 mod context;
 mod cork_state;
+mod deadline;
+mod device_cache;
+mod held_ports;
 mod intern;
+mod server_caps;
+mod shared;
 mod stream;
+mod stream_state;
 
-pub use self::context::PulseContext;
-use self::intern::Intern;
+pub use self::context::{ConnectOptions, DefaultDeviceChangedCallback, PulseContext};
 pub use self::stream::PulseStream;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 
 // helper to convert *const c_char to Option<CStr>
@@ -22,3 +28,87 @@ fn try_cstr_from<'str>(s: *const c_char) -> Option<&'str CStr> {
         Some(unsafe { CStr::from_ptr(s) })
     }
 }
+
+// Device IDs of the form "<device>#<port>" select a port of the device.
+const PORT_SEPARATOR: u8 = b'#';
+
+fn device_port_id(device: &CStr, port: &CStr) -> CString {
+    let mut id = device.to_bytes().to_vec();
+    id.push(PORT_SEPARATOR);
+    id.extend_from_slice(port.to_bytes());
+    CString::new(id).expect("device and port names contain no NUL")
+}
+
+// Split a device ID into the device name and the port, if any, at the last
+// separator. Device names may contain the separator too, so the result is
+// only a candidate to check against the server's devices.
+fn split_device_port(id: &CStr) -> (CString, Option<CString>) {
+    let bytes = id.to_bytes();
+    match bytes.iter().rposition(|&b| b == PORT_SEPARATOR) {
+        Some(i) => (
+            CString::new(&bytes[..i]).unwrap(),
+            Some(CString::new(&bytes[i + 1..]).unwrap()),
+        ),
+        None => (id.to_owned(), None),
+    }
+}
+
+// The ID of the entry enumeration marks as preferred for a device: its
+// active port's if it has several.
+fn active_port_id(
+    device: &CStr,
+    ports: *mut *mut pulse_ffi::pa_port_info,
+    n_ports: u32,
+    active_port: *mut pulse_ffi::pa_port_info,
+) -> CString {
+    let active_port = unsafe { active_port.as_ref() }.and_then(|p| try_cstr_from(p.name));
+    match active_port {
+        Some(port) if !ports.is_null() && n_ports >= 2 => device_port_id(device, port),
+        _ => device.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{active_port_id, device_port_id, split_device_port};
+    use pulse_ffi::pa_port_info;
+    use std::ffi::CString;
+    use std::ptr;
+
+    #[test]
+    fn device_port_roundtrip() {
+        let device = CString::new("alsa_output.pci-0000_00_1f.3.analog-stereo").unwrap();
+        let port = CString::new("analog-output-headphones").unwrap();
+        let id = device_port_id(&device, &port);
+        assert_eq!(split_device_port(&id), (device.clone(), Some(port.clone())));
+        assert_eq!(split_device_port(&device), (device, None));
+
+        let device = CString::new("bluez_output.a#b.1").unwrap();
+        let id = device_port_id(&device, &port);
+        assert_eq!(split_device_port(&id), (device, Some(port)));
+    }
+
+    #[test]
+    fn preferred_port_id() {
+        let device = CString::new("alsa_output.pci-0000_00_1f.3.analog-stereo").unwrap();
+        let speaker = CString::new("analog-output-speaker").unwrap();
+        let headphones = CString::new("analog-output-headphones").unwrap();
+        let mut speaker_info = pa_port_info {
+            name: speaker.as_ptr(),
+            ..Default::default()
+        };
+        let mut headphones_info = pa_port_info {
+            name: headphones.as_ptr(),
+            ..Default::default()
+        };
+        let mut ports = [&mut speaker_info as *mut _, &mut headphones_info as *mut _];
+
+        let id = active_port_id(&device, ports.as_mut_ptr(), 2, ports[1]);
+        assert_eq!(id, device_port_id(&device, &headphones));
+        // Single port devices aren't split.
+        let id = active_port_id(&device, ports.as_mut_ptr(), 1, ports[0]);
+        assert_eq!(id, device);
+        let id = active_port_id(&device, ptr::null_mut(), 0, ptr::null_mut());
+        assert_eq!(id, device);
+    }
+}
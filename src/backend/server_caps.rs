@@ -0,0 +1,189 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use pulse;
+use pulse_ffi::{pa_usec_t, PA_USEC_PER_MSEC};
+use semver::Version;
+use std::ffi::CStr;
+
+/// The implementation of the server the context is connected to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerKind {
+    PulseAudio,
+    /// pipewire-pulse, with PipeWire's own version.
+    PipeWire(Option<Version>),
+    /// Reports itself under another name, assumed to behave like
+    /// PulseAudio.
+    Other,
+}
+
+/// What the server is and how it behaves, learnt from its info once
+/// connected. Until then, the server is assumed to be PulseAudio.
+#[derive(Clone, Debug)]
+pub struct ServerCapabilities {
+    pub kind: ServerKind,
+    /// Version reported by the server. pipewire-pulse reports the
+    /// PulseAudio version it is compatible with.
+    pub version: Option<Version>,
+    pub protocol_version: u32,
+    /// Version of the client library, which has to support a feature as
+    /// well.
+    pub library_version: Option<Version>,
+}
+
+impl Default for ServerCapabilities {
+    fn default() -> Self {
+        ServerCapabilities {
+            kind: ServerKind::PulseAudio,
+            version: None,
+            protocol_version: 0,
+            library_version: None,
+        }
+    }
+}
+
+// Lenient parse of versions such as "16.1" or "15.0.0-rebootstrapped".
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?;
+    let mut numbers = version.split('.').map(|n| n.parse::<u64>().ok());
+    let major = numbers.next()??;
+    let minor = numbers.next().unwrap_or(Some(0))?;
+    let patch = numbers.next().unwrap_or(Some(0))?;
+    Some(Version::new(major, minor, patch))
+}
+
+impl ServerCapabilities {
+    pub fn new(name: &CStr, version: &CStr, protocol_version: u32, library_version: &CStr) -> Self {
+        let name = name.to_string_lossy();
+        let kind = if let Some(i) = name.find("PipeWire") {
+            // e.g. "PulseAudio (on PipeWire 0.3.65)"
+            ServerKind::PipeWire(parse_version(name[i + "PipeWire".len()..].trim_start()))
+        } else if name.eq_ignore_ascii_case("pulseaudio") {
+            ServerKind::PulseAudio
+        } else {
+            ServerKind::Other
+        };
+        ServerCapabilities {
+            kind,
+            version: parse_version(&version.to_string_lossy()),
+            protocol_version,
+            library_version: parse_version(&library_version.to_string_lossy()),
+        }
+    }
+
+    pub fn is_pipewire(&self) -> bool {
+        matches!(self.kind, ServerKind::PipeWire(_))
+    }
+
+    /// Whether port availability is reported, from protocol 24 and
+    /// library 2.0 (PulseAudio 2.0) on.
+    pub fn port_availability(&self) -> bool {
+        self.protocol_version >= 24
+            && self
+                .library_version
+                .as_ref()
+                .is_some_and(|v| *v >= Version::new(2, 0, 0))
+    }
+
+    /// Whether a sink's `FLAT_VOLUME` flag means that the volume of a sink
+    /// input changes the sink's. pipewire-pulse sets the flag, but keeps
+    /// stream volumes separate.
+    pub fn flat_volume(&self, flags: pulse::SinkFlags) -> bool {
+        flags.contains(pulse::SinkFlags::FLAT_VOLUME) && !self.is_pipewire()
+    }
+
+    /// How long to wait after writing the last frames before reporting the
+    /// stream drained, given the stream's latency. PulseAudio's
+    /// `pa_stream_drain` is unreliable (bug 866) and its latency estimate
+    /// can be short, so it gets a margin of double the latency. PipeWire's
+    /// is accurate.
+    pub fn drain_delay(&self, latency: pa_usec_t) -> pa_usec_t {
+        if self.is_pipewire() {
+            latency + 10 * PA_USEC_PER_MSEC
+        } else {
+            2 * latency
+        }
+    }
+
+    /// Latency to assume is reachable when the default sink doesn't tell.
+    pub fn fallback_min_latency(&self) -> pa_usec_t {
+        if self.is_pipewire() {
+            // A 512 frame quantum at 48kHz is 10.7ms, rounded down.
+            10 * PA_USEC_PER_MSEC
+        } else {
+            // According to PulseAudio developers, this is a safe minimum.
+            25 * PA_USEC_PER_MSEC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ServerCapabilities, ServerKind};
+    use pulse;
+    use semver::Version;
+    use std::ffi::CStr;
+
+    fn caps(name: &[u8], version: &[u8], protocol_version: u32) -> ServerCapabilities {
+        ServerCapabilities::new(
+            CStr::from_bytes_with_nul(name).unwrap(),
+            CStr::from_bytes_with_nul(version).unwrap(),
+            protocol_version,
+            CStr::from_bytes_with_nul(b"16.1.0\0").unwrap(),
+        )
+    }
+
+    #[test]
+    fn detect_server() {
+        let pa = caps(b"pulseaudio\0", b"16.1\0", 35);
+        assert_eq!(pa.kind, ServerKind::PulseAudio);
+        assert_eq!(pa.version, Some(Version::new(16, 1, 0)));
+
+        let pw = caps(b"PulseAudio (on PipeWire 0.3.65)\0", b"15.0.0\0", 35);
+        assert_eq!(pw.kind, ServerKind::PipeWire(Some(Version::new(0, 3, 65))));
+        assert_eq!(pw.version, Some(Version::new(15, 0, 0)));
+        assert!(pw.is_pipewire());
+
+        let other = caps(b"some-server\0", b"unknown\0", 13);
+        assert_eq!(other.kind, ServerKind::Other);
+        assert_eq!(other.version, None);
+        assert!(!other.port_availability());
+    }
+
+    #[test]
+    fn port_availability() {
+        assert!(caps(b"pulseaudio\0", b"16.1\0", 35).port_availability());
+        assert!(!caps(b"pulseaudio\0", b"1.1\0", 23).port_availability());
+
+        // An old library on a recent server.
+        let old_library = ServerCapabilities::new(
+            CStr::from_bytes_with_nul(b"pulseaudio\0").unwrap(),
+            CStr::from_bytes_with_nul(b"16.1\0").unwrap(),
+            35,
+            CStr::from_bytes_with_nul(b"1.1.0\0").unwrap(),
+        );
+        assert!(!old_library.port_availability());
+        assert!(!ServerCapabilities::default().port_availability());
+    }
+
+    #[test]
+    fn quirks() {
+        let pa = caps(b"pulseaudio\0", b"16.1\0", 35);
+        let pw = caps(b"PulseAudio (on PipeWire 1.0.5)\0", b"15.0.0\0", 35);
+        let flat = pulse::SinkFlags::FLAT_VOLUME | pulse::SinkFlags::HW_VOLUME_CTRL;
+
+        assert!(pa.flat_volume(flat));
+        assert!(!pw.flat_volume(flat));
+        assert!(!pa.flat_volume(pulse::SinkFlags::empty()));
+
+        assert_eq!(pa.drain_delay(40_000), 80_000);
+        assert_eq!(pw.drain_delay(40_000), 50_000);
+
+        assert_eq!(pa.fallback_min_latency(), 25_000);
+        assert_eq!(pw.fallback_min_latency(), 10_000);
+    }
+}
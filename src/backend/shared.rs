@@ -0,0 +1,190 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use backend::{ConnectOptions, PulseContext};
+use pulse;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Mutex;
+
+/// A mainloop thread and server connection shared by the `PulseContext`s
+/// created with `ConnectOptions::shared`, its members.
+///
+/// Each member holds a copy of the mainloop, which it must not free, and its
+/// own reference on the connection. When the connection fails, the first
+/// member to reinitialize replaces it, and the others adopt the replacement
+/// when they reinitialize in turn.
+#[derive(Debug)]
+pub struct SharedConnection {
+    mainloop: pulse::ThreadedMainloop,
+    context: Option<pulse::Context>,
+    // Members are added and removed with the mainloop locked, so that the
+    // callbacks below can walk them.
+    members: Vec<*mut PulseContext>,
+    server: Option<CString>,
+    flags: pulse::ContextFlags,
+}
+
+struct Shared(*mut SharedConnection);
+
+// Only dereferenced with the mutex held, or by members that keep it alive.
+unsafe impl Send for Shared {}
+
+static SHARED: Mutex<Shared> = Mutex::new(Shared(ptr::null_mut()));
+
+impl SharedConnection {
+    /// Adds `member` to the shared connection, creating it and starting its
+    /// mainloop for the first member. Returns `None` if that fails, or if the
+    /// existing connection goes to another server.
+    pub fn join(
+        member: *mut PulseContext,
+        options: &ConnectOptions,
+    ) -> Option<*mut SharedConnection> {
+        let mut shared = SHARED.lock().unwrap();
+        if shared.0.is_null() {
+            let mainloop = pulse::ThreadedMainloop::new();
+            if mainloop.is_null() || mainloop.start().is_err() {
+                cubeb_log!("Error: couldn't start the shared mainloop");
+                return None;
+            }
+            shared.0 = Box::into_raw(Box::new(SharedConnection {
+                mainloop,
+                context: None,
+                members: Vec::new(),
+                server: options.server.clone(),
+                flags: options.flags,
+            }));
+        }
+
+        let conn = unsafe { &mut *shared.0 };
+        if conn.server != options.server || conn.flags.bits() != options.flags.bits() {
+            cubeb_log!("Not sharing the connection to {:?}", conn.server);
+            return None;
+        }
+
+        conn.mainloop.lock();
+        conn.members.push(member);
+        conn.mainloop.unlock();
+        Some(shared.0)
+    }
+
+    /// Removes `member`, tearing the connection down after the last one,
+    /// or narrowing the subscription to the events the others need. The
+    /// member must have dropped its reference on the connection.
+    pub fn leave(shared: *mut SharedConnection, member: *mut PulseContext) {
+        fn subscribed(_: &pulse::Context, success: i32, _: *mut c_void) {
+            if success != 1 {
+                cubeb_log!("subscribe_success ignored failure: {}", success);
+            }
+        }
+
+        let mut registry = SHARED.lock().unwrap();
+        debug_assert_eq!(registry.0, shared);
+        let conn = unsafe { &mut *shared };
+
+        conn.mainloop.lock();
+        let previous_mask = conn.subscription_mask();
+        conn.members.retain(|&m| m != member);
+        let last = conn.members.is_empty();
+        if last {
+            if let Some(context) = conn.context.take() {
+                context.clear_state_callback();
+                context.disconnect();
+                context.unref();
+            }
+        } else {
+            let mask = conn.subscription_mask();
+            if mask.bits() != previous_mask.bits() {
+                if let Some(context) = conn.context() {
+                    // Not waited on: nothing depends on the events dropped.
+                    if let Err(e) = context.subscribe(mask, subscribed, ptr::null_mut()) {
+                        cubeb_log!("subscribe ignored failure: {}", e);
+                    }
+                    context.unref();
+                }
+            }
+        }
+        conn.mainloop.unlock();
+
+        if last {
+            conn.mainloop.stop();
+            let _ = unsafe { Box::from_raw(shared) };
+            registry.0 = ptr::null_mut();
+        }
+    }
+
+    /// A copy of the mainloop, to be forgotten rather than dropped.
+    pub fn mainloop(&self) -> pulse::ThreadedMainloop {
+        unsafe { pulse::ThreadedMainloop::from_raw_ptr(self.mainloop.raw_mut()) }
+    }
+
+    /// A new reference on the connection, unless there's none or it failed.
+    /// Must be called with the mainloop locked.
+    pub fn context(&self) -> Option<pulse::Context> {
+        self.context
+            .as_ref()
+            .filter(|c| c.get_state().is_good())
+            .map(pulse::Context::add_ref)
+    }
+
+    /// Makes `context`, a ready connection set up with the callbacks below,
+    /// the shared one in place of a failed one. Must be called with the
+    /// mainloop locked.
+    pub fn set_context(&mut self, context: &pulse::Context) {
+        if let Some(previous) = self.context.replace(context.add_ref()) {
+            previous.clear_state_callback();
+            previous.disconnect();
+            previous.unref();
+        }
+    }
+
+    /// Events to subscribe to for all the members. Must be called with the
+    /// mainloop locked.
+    pub fn subscription_mask(&self) -> pulse::SubscriptionMask {
+        self.members
+            .iter()
+            .fold(pulse::SubscriptionMask::empty(), |mask, &m| {
+                mask | unsafe { &*m }.subscription_mask()
+            })
+    }
+
+    fn members_of(&self, c: &pulse::Context) -> Vec<*mut PulseContext> {
+        let c: *const _ = c.raw_mut();
+        self.members
+            .iter()
+            .cloned()
+            .filter(|&m| match unsafe { &(*m).context } {
+                Some(context) => ptr::eq(context.raw_mut(), c),
+                None => false,
+            })
+            .collect()
+    }
+
+    /// State callback of the shared connection.
+    pub fn context_state(c: &pulse::Context, u: *mut c_void) {
+        let conn = unsafe { &*(u as *const SharedConnection) };
+        if !c.get_state().is_good() {
+            for m in conn.members_of(c) {
+                unsafe { (*m).error = true };
+            }
+        }
+        conn.mainloop.signal();
+    }
+
+    /// Subscription callback of the shared connection, dispatching events to
+    /// every member using it.
+    pub fn subscription_event(
+        c: &pulse::Context,
+        event: pulse::SubscriptionEvent,
+        index: u32,
+        u: *mut c_void,
+    ) {
+        let conn = unsafe { &*(u as *const SharedConnection) };
+        for m in conn.members_of(c) {
+            PulseContext::subscription_event(c, event, index, m as *mut c_void);
+        }
+    }
+}
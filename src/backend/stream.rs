@@ -4,23 +4,22 @@
 // accompanying file LICENSE for details.
 
 use backend::cork_state::CorkState;
+use backend::stream_state::{AtomicStreamState, StreamState};
 use backend::*;
 use cubeb_backend::{
-    ffi, log_enabled, ChannelLayout, DeviceId, DeviceRef, Error, InputProcessingParams, Result,
-    SampleFormat, StreamOps, StreamParamsRef, StreamPrefs,
+    ffi, log_enabled, ChannelLayout, DeviceId, DeviceRef, DeviceType, Error, InputProcessingParams,
+    Result, SampleFormat, StreamOps, StreamParamsRef, StreamPrefs,
 };
 use pulse::{self, CVolumeExt, ChannelMapExt, SampleSpecExt, StreamLatency, USecExt};
 use pulse_ffi::*;
 use ringbuf::RingBuffer;
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_long, c_void};
+use std::os::raw::{c_char, c_long, c_void};
 use std::slice;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
 use std::{mem, ptr};
 
-use self::LinearInputBuffer::*;
-use self::RingBufferConsumer::*;
-use self::RingBufferProducer::*;
+use self::BufferManager::*;
 
 const PULSE_NO_GAIN: f32 = -1.0;
 
@@ -125,25 +124,68 @@ impl Drop for Device {
     }
 }
 
-enum RingBufferConsumer {
-    IntegerRingBufferConsumer(ringbuf::Consumer<i16>),
-    FloatRingBufferConsumer(ringbuf::Consumer<f32>),
+/// Input side of a duplex stream.
+///
+/// Captured samples are pushed into the ring from `read_data` and handed to
+/// the data callback from `write_data`. When the requested samples are
+/// contiguous in the ring storage, the callback reads them in place; the
+/// linear buffer is only used when the request wraps around the end of the
+/// ring or more samples are requested than are buffered.
+struct InputRing<T> {
+    producer: ringbuf::Producer<T>,
+    consumer: ringbuf::Consumer<T>,
+    linear_input_buffer: Vec<T>,
+    // Samples lent to the data callback by `acquire_input_data` that are
+    // still in the ring, and must be discarded by `release_input_data`.
+    lent_samples: usize,
 }
 
-enum RingBufferProducer {
-    IntegerRingBufferProducer(ringbuf::Producer<i16>),
-    FloatRingBufferProducer(ringbuf::Producer<f32>),
-}
+impl<T: Copy + Default> InputRing<T> {
+    fn new(capacity: usize) -> Self {
+        let (producer, consumer) = RingBuffer::<T>::new(capacity).split();
+        InputRing {
+            producer,
+            consumer,
+            linear_input_buffer: Vec::with_capacity(capacity),
+            lent_samples: 0,
+        }
+    }
+
+    fn push(&mut self, input_data: *const c_void, nsamples: usize) {
+        let input = unsafe { slice::from_raw_parts::<T>(input_data as *const T, nsamples) };
+        // we don't do anything in particular if we can't push everything
+        self.producer.push_slice(input);
+    }
+
+    fn acquire(&mut self, nsamples: usize) -> *const c_void {
+        debug_assert_eq!(self.lent_samples, 0);
+        let (head, _) = self.consumer.as_slices();
+        if head.len() >= nsamples {
+            self.lent_samples = nsamples;
+            return head.as_ptr() as *const c_void;
+        }
+
+        self.linear_input_buffer.resize(nsamples, T::default());
+        let read = self.consumer.pop_slice(&mut self.linear_input_buffer);
+        self.linear_input_buffer[read..].fill(T::default());
+        self.linear_input_buffer.as_ptr() as *const c_void
+    }
 
-enum LinearInputBuffer {
-    IntegerLinearInputBuffer(Vec<i16>),
-    FloatLinearInputBuffer(Vec<f32>),
+    fn release(&mut self) {
+        self.consumer.discard(self.lent_samples);
+        self.lent_samples = 0;
+    }
+
+    fn trim(&mut self, final_size: usize) {
+        let length = self.consumer.len();
+        assert!(final_size <= length);
+        self.consumer.discard(length - final_size);
+    }
 }
 
-struct BufferManager {
-    consumer: RingBufferConsumer,
-    producer: RingBufferProducer,
-    linear_input_buffer: LinearInputBuffer,
+enum BufferManager {
+    IntegerBufferManager(InputRing<i16>),
+    FloatBufferManager(InputRing<f32>),
 }
 
 impl BufferManager {
@@ -151,107 +193,47 @@ impl BufferManager {
     // either the input or output sample-spec here.
     fn new(input_buffer_size: usize, sample_spec: &pulse::SampleSpec) -> BufferManager {
         if sample_spec.format == PA_SAMPLE_S16BE || sample_spec.format == PA_SAMPLE_S16LE {
-            let ring = RingBuffer::<i16>::new(input_buffer_size);
-            let (prod, cons) = ring.split();
-            BufferManager {
-                producer: IntegerRingBufferProducer(prod),
-                consumer: IntegerRingBufferConsumer(cons),
-                linear_input_buffer: IntegerLinearInputBuffer(Vec::<i16>::with_capacity(
-                    input_buffer_size,
-                )),
-            }
+            IntegerBufferManager(InputRing::new(input_buffer_size))
         } else {
-            let ring = RingBuffer::<f32>::new(input_buffer_size);
-            let (prod, cons) = ring.split();
-            BufferManager {
-                producer: FloatRingBufferProducer(prod),
-                consumer: FloatRingBufferConsumer(cons),
-                linear_input_buffer: FloatLinearInputBuffer(Vec::<f32>::with_capacity(
-                    input_buffer_size,
-                )),
-            }
+            FloatBufferManager(InputRing::new(input_buffer_size))
         }
     }
 
     fn push_input_data(&mut self, input_data: *const c_void, read_samples: usize) {
-        match &mut self.producer {
-            RingBufferProducer::FloatRingBufferProducer(p) => {
-                let input_data =
-                    unsafe { slice::from_raw_parts::<f32>(input_data as *const f32, read_samples) };
-                // we don't do anything in particular if we can't push everything
-                p.push_slice(input_data);
-            }
-            RingBufferProducer::IntegerRingBufferProducer(p) => {
-                let input_data =
-                    unsafe { slice::from_raw_parts::<i16>(input_data as *const i16, read_samples) };
-                p.push_slice(input_data);
-            }
+        match self {
+            IntegerBufferManager(r) => r.push(input_data, read_samples),
+            FloatBufferManager(r) => r.push(input_data, read_samples),
         }
     }
 
-    fn pull_input_data(&mut self, input_data: *mut c_void, needed_samples: usize) {
-        match &mut self.consumer {
-            IntegerRingBufferConsumer(p) => {
-                let input: &mut [i16] = unsafe {
-                    slice::from_raw_parts_mut::<i16>(input_data as *mut i16, needed_samples)
-                };
-                let read = p.pop_slice(input);
-                if read < needed_samples {
-                    for i in 0..(needed_samples - read) {
-                        input[read + i] = 0;
-                    }
-                }
-            }
-            FloatRingBufferConsumer(p) => {
-                let input: &mut [f32] = unsafe {
-                    slice::from_raw_parts_mut::<f32>(input_data as *mut f32, needed_samples)
-                };
-                let read = p.pop_slice(input);
-                if read < needed_samples {
-                    for i in 0..(needed_samples - read) {
-                        input[read + i] = 0.;
-                    }
-                }
-            }
+    /// Returns a pointer to `nsamples` contiguous input samples, valid until
+    /// `release_input_data` is called. Missing samples are zero-filled.
+    fn acquire_input_data(&mut self, nsamples: usize) -> *const c_void {
+        match self {
+            IntegerBufferManager(r) => r.acquire(nsamples),
+            FloatBufferManager(r) => r.acquire(nsamples),
         }
     }
 
-    fn get_linear_input_data(&mut self, nsamples: usize) -> *const c_void {
-        let p = match &mut self.linear_input_buffer {
-            LinearInputBuffer::IntegerLinearInputBuffer(b) => {
-                b.resize(nsamples, 0);
-                b.as_mut_ptr() as *mut c_void
-            }
-            LinearInputBuffer::FloatLinearInputBuffer(b) => {
-                b.resize(nsamples, 0.);
-                b.as_mut_ptr() as *mut c_void
-            }
-        };
-        self.pull_input_data(p, nsamples);
-
-        p
+    /// Consumes the samples returned by the last `acquire_input_data` call.
+    fn release_input_data(&mut self) {
+        match self {
+            IntegerBufferManager(r) => r.release(),
+            FloatBufferManager(r) => r.release(),
+        }
     }
 
     pub fn trim(&mut self, final_size: usize) {
-        match &self.linear_input_buffer {
-            LinearInputBuffer::IntegerLinearInputBuffer(b) => {
-                let length = b.len();
-                assert!(final_size <= length);
-                let nframes_to_pop = length - final_size;
-                self.get_linear_input_data(nframes_to_pop);
-            }
-            LinearInputBuffer::FloatLinearInputBuffer(b) => {
-                let length = b.len();
-                assert!(final_size <= length);
-                let nframes_to_pop = length - final_size;
-                self.get_linear_input_data(nframes_to_pop);
-            }
+        match self {
+            IntegerBufferManager(r) => r.trim(final_size),
+            FloatBufferManager(r) => r.trim(final_size),
         }
     }
-    pub fn available_samples(&mut self) -> usize {
-        match &self.linear_input_buffer {
-            LinearInputBuffer::IntegerLinearInputBuffer(b) => b.len(),
-            LinearInputBuffer::FloatLinearInputBuffer(b) => b.len(),
+
+    pub fn available_samples(&self) -> usize {
+        match self {
+            IntegerBufferManager(r) => r.consumer.len(),
+            FloatBufferManager(r) => r.consumer.len(),
         }
     }
 }
@@ -262,6 +244,15 @@ impl std::fmt::Debug for BufferManager {
     }
 }
 
+/// Counters describing the health of a stream, logged when it is destroyed.
+#[derive(Debug, Default)]
+struct StreamStats {
+    /// Holes reported by the record stream.
+    input_holes: AtomicUsize,
+    /// Frames of silence delivered in place of those holes.
+    input_hole_frames: AtomicUsize,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct PulseStream<'ctx> {
@@ -276,10 +267,19 @@ pub struct PulseStream<'ctx> {
     input_sample_spec: pulse::SampleSpec,
     // output frames count excluding pre-buffering
     output_frame_count: AtomicUsize,
-    shutdown: bool,
-    volume: f32,
-    state: ffi::cubeb_state,
+    // f32 gain, stored as its bit pattern. PULSE_NO_GAIN when unused.
+    volume: AtomicU32,
+    state: AtomicStreamState,
     input_buffer_manager: Option<BufferManager>,
+    // zeroed bytes handed out in place of holes in the record stream
+    input_silence: Vec<u8>,
+    // card whose profile was switched for the input stream
+    input_card: Option<u32>,
+    // devices whose port is held for the stream
+    held_ports: Vec<(DeviceType, CString)>,
+    // device IDs the stream was opened on, kept valid until it's destroyed
+    device_ids: Vec<*const c_char>,
+    stats: StreamStats,
 }
 
 impl<'ctx> PulseStream<'ctx> {
@@ -300,7 +300,7 @@ impl<'ctx> PulseStream<'ctx> {
             let stm = unsafe { &mut *(u as *mut PulseStream) };
             if !s.get_state().is_good() {
                 cubeb_alog!("Calling error callback");
-                stm.state_change_callback(ffi::CUBEB_STATE_ERROR);
+                stm.transition(StreamState::Error);
             }
             stm.context.mainloop.signal();
         }
@@ -321,14 +321,27 @@ impl<'ctx> PulseStream<'ctx> {
 
             cubeb_alogv!("Input callback buffer size {}", nbytes);
             let stm = unsafe { &mut *(u as *mut PulseStream) };
-            if stm.shutdown {
+            if stm.state.load() != StreamState::Started {
                 return;
             }
 
             let mut read_data: *const c_void = ptr::null();
             let mut read_size: usize = 0;
             while read_from_input(s, &mut read_data, &mut read_size) > 0 {
-                /* read_data can be NULL in case of a hole. */
+                /* read_data can be NULL in case of a hole. Deliver the same
+                 * amount of silence so the input timeline is preserved. */
+                if read_data.is_null() && read_size > 0 {
+                    stm.stats.input_holes.fetch_add(1, Ordering::Relaxed);
+                    stm.stats.input_hole_frames.fetch_add(
+                        read_size / stm.input_sample_spec.frame_size(),
+                        Ordering::Relaxed,
+                    );
+                    if stm.input_silence.len() < read_size {
+                        stm.input_silence.resize(read_size, 0);
+                    }
+                    read_data = stm.input_silence.as_ptr() as *const c_void;
+                }
+
                 if !read_data.is_null() {
                     let in_frame_size = stm.input_sample_spec.frame_size();
                     let read_frames = read_size / in_frame_size;
@@ -352,20 +365,22 @@ impl<'ctx> PulseStream<'ctx> {
                             )
                         };
 
-                        if got < 0 || got as usize != read_frames {
+                        if got < 0 {
                             let _ = s.cancel_write();
-                            stm.shutdown = true;
-                            if got < 0 {
-                                unsafe {
-                                    stm.state_callback.unwrap()(
-                                        stm as *mut _ as *mut _,
-                                        stm.user_ptr,
-                                        ffi::CUBEB_STATE_ERROR,
-                                    );
-                                }
-                            }
+                            stm.transition(StreamState::Error);
                             break;
                         }
+
+                        if (got as usize) < read_frames {
+                            // A short return ends an input-only stream. Cork the record
+                            // stream so that `start` can resume it. We're on the mainloop
+                            // thread, so don't wait for the operation to complete.
+                            cubeb_logv!("Draining input {} < {}", got, read_frames);
+                            let _ = s.drop();
+                            let _ = s.cork(1, stream_success, u);
+                            stm.transition(StreamState::Drained);
+                            return;
+                        }
                     }
                 }
 
@@ -373,7 +388,7 @@ impl<'ctx> PulseStream<'ctx> {
                     let _ = s.drop();
                 }
 
-                if stm.shutdown {
+                if stm.state.load() != StreamState::Started {
                     return;
                 }
             }
@@ -382,7 +397,7 @@ impl<'ctx> PulseStream<'ctx> {
         fn write_data(_: &pulse::Stream, nbytes: usize, u: *mut c_void) {
             cubeb_alogv!("Output callback to be written buffer size {}", nbytes);
             let stm = unsafe { &mut *(u as *mut PulseStream) };
-            if stm.shutdown || stm.state != ffi::CUBEB_STATE_STARTED {
+            if stm.state.load() != StreamState::Started {
                 return;
             }
 
@@ -404,8 +419,12 @@ impl<'ctx> PulseStream<'ctx> {
                     }
                 }
 
-                let p = input_buffer_manager.get_linear_input_data(nsamples_input);
+                let p = input_buffer_manager.acquire_input_data(nsamples_input);
                 stm.trigger_user_callback(p, nbytes);
+                stm.input_buffer_manager
+                    .as_mut()
+                    .unwrap()
+                    .release_input_data();
             } else {
                 // Output/playback only operation.
                 // Write directly to output
@@ -425,18 +444,32 @@ impl<'ctx> PulseStream<'ctx> {
             output_sample_spec: pulse::SampleSpec::default(),
             input_sample_spec: pulse::SampleSpec::default(),
             output_frame_count: AtomicUsize::new(0),
-            shutdown: false,
-            volume: PULSE_NO_GAIN,
-            state: ffi::CUBEB_STATE_ERROR,
+            volume: AtomicU32::new(PULSE_NO_GAIN.to_bits()),
+            state: AtomicStreamState::new(StreamState::Stopped),
             input_buffer_manager: None,
+            input_silence: Vec::new(),
+            input_card: None,
+            held_ports: Vec::new(),
+            device_ids: Vec::new(),
+            stats: StreamStats::default(),
         });
 
         if let Some(ref context) = stm.context.context {
             stm.context.mainloop.lock();
 
+            for id in [input_device, output_device] {
+                let id = id as *const c_char;
+                if stm.context.retain_device_id(id) {
+                    stm.device_ids.push(id);
+                }
+            }
+
+            // The sink the output stream is opened on, if not the default.
+            let mut output_device_name = None;
+
             // Setup output stream
             if let Some(stream_params) = output_stream_params {
-                match PulseStream::stream_init(context, stream_params, stream_name) {
+                match PulseStream::stream_init(context, stream_params, stream_name, None) {
                     Ok(s) => {
                         stm.output_sample_spec = *s.get_sample_spec();
 
@@ -453,7 +486,23 @@ impl<'ctx> PulseStream<'ctx> {
                             tlength: buffer_size_bytes * 2,
                             minreq: buffer_size_bytes / 4,
                         };
-                        let device_name = super::try_cstr_from(output_device as *const _);
+                        let device = match super::try_cstr_from(output_device as *const _) {
+                            Some(id) => match stm.context.select_port(DeviceType::OUTPUT, id) {
+                                Ok((name, held)) => {
+                                    if held {
+                                        stm.held_ports.push((DeviceType::OUTPUT, name.clone()));
+                                    }
+                                    Some(name)
+                                }
+                                Err(e) => {
+                                    stm.context.mainloop.unlock();
+                                    stm.destroy();
+                                    return Err(e);
+                                }
+                            },
+                            None => None,
+                        };
+                        let device_name = device.as_deref();
                         let mut stream_flags = pulse::StreamFlags::AUTO_TIMING_UPDATE
                             | pulse::StreamFlags::INTERPOLATE_TIMING
                             | pulse::StreamFlags::START_CORKED
@@ -466,6 +515,7 @@ impl<'ctx> PulseStream<'ctx> {
                             stream_flags |= pulse::StreamFlags::DONT_MOVE;
                         }
                         let _ = s.connect_playback(device_name, &battr, stream_flags, None, None);
+                        output_device_name = device.clone();
 
                         stm.output_stream = Some(s);
                     }
@@ -480,7 +530,22 @@ impl<'ctx> PulseStream<'ctx> {
 
             // Set up input stream
             if let Some(stream_params) = input_stream_params {
-                match PulseStream::stream_init(context, stream_params, stream_name) {
+                // Record in the default source's channel order when the
+                // layout is left to us.
+                let default_map = if input_device.is_null() {
+                    stm.context
+                        .default_source_info
+                        .as_ref()
+                        .map(|info| info.channel_map)
+                } else {
+                    None
+                };
+                match PulseStream::stream_init(
+                    context,
+                    stream_params,
+                    stream_name,
+                    default_map.as_ref(),
+                ) {
                     Ok(s) => {
                         stm.input_sample_spec = *s.get_sample_spec();
 
@@ -496,7 +561,23 @@ impl<'ctx> PulseStream<'ctx> {
                             tlength: buffer_size_bytes,
                             minreq: buffer_size_bytes,
                         };
-                        let device_name = super::try_cstr_from(input_device as *const _);
+                        let device = match super::try_cstr_from(input_device as *const _) {
+                            Some(id) => match stm.context.select_port(DeviceType::INPUT, id) {
+                                Ok((name, held)) => {
+                                    if held {
+                                        stm.held_ports.push((DeviceType::INPUT, name.clone()));
+                                    }
+                                    Some(name)
+                                }
+                                Err(e) => {
+                                    stm.context.mainloop.unlock();
+                                    stm.destroy();
+                                    return Err(e);
+                                }
+                            },
+                            None => None,
+                        };
+                        let device_name = device.as_deref();
                         let mut stream_flags = pulse::StreamFlags::AUTO_TIMING_UPDATE
                             | pulse::StreamFlags::INTERPOLATE_TIMING
                             | pulse::StreamFlags::START_CORKED
@@ -508,7 +589,21 @@ impl<'ctx> PulseStream<'ctx> {
                         {
                             stream_flags |= pulse::StreamFlags::DONT_MOVE;
                         }
-                        let _ = s.connect_record(device_name, &battr, stream_flags);
+                        let mut card_source = None;
+                        if stm.context.switch_bluetooth_profiles {
+                            if let Some((card, source)) = stm
+                                .context
+                                .acquire_input_profile(device_name, output_device_name.as_deref())
+                            {
+                                stm.input_card = Some(card);
+                                card_source = source;
+                            }
+                        }
+                        let _ = s.connect_record(
+                            card_source.as_deref().or(device_name),
+                            &battr,
+                            stream_flags,
+                        );
 
                         stm.input_stream = Some(s);
                     }
@@ -599,11 +694,31 @@ impl<'ctx> PulseStream<'ctx> {
             }
 
             if let Some(stm) = self.input_stream.take() {
+                let holes = self.stats.input_holes.load(Ordering::Relaxed);
+                if holes > 0 {
+                    cubeb_log!(
+                        "Input stream had {} holes, filled with {} frames of silence",
+                        holes,
+                        self.stats.input_hole_frames.load(Ordering::Relaxed)
+                    );
+                }
                 stm.clear_state_callback();
                 stm.clear_read_callback();
                 let _ = stm.disconnect();
                 stm.unref();
             }
+
+            if let Some(card) = self.input_card.take() {
+                self.context.release_input_profile(card);
+            }
+
+            for (devtype, device) in self.held_ports.drain(..) {
+                self.context.release_port(devtype, &device);
+            }
+
+            for id in self.device_ids.drain(..) {
+                self.context.release_device_id(id);
+            }
         }
         self.context.mainloop.unlock();
     }
@@ -619,7 +734,7 @@ impl StreamOps for PulseStream<'_> {
     fn start(&mut self) -> Result<()> {
         fn output_preroll(_: &pulse::MainloopApi, u: *mut c_void) {
             let stm = unsafe { &mut *(u as *mut PulseStream) };
-            if !stm.shutdown {
+            if stm.state.load() == StreamState::Started {
                 let size = stm
                     .output_stream
                     .as_ref()
@@ -627,14 +742,36 @@ impl StreamOps for PulseStream<'_> {
                 stm.trigger_user_callback(std::ptr::null(), size);
             }
         }
-        self.shutdown = false;
-        self.cork(CorkState::uncork() | CorkState::notify());
+
+        if self.state.load() == StreamState::Error {
+            cubeb_log!("Error: can't start a stream in the error state");
+            return Err(Error::error());
+        }
+
+        if self.state.load() == StreamState::Draining {
+            // Let the drain complete, then restart from `Drained` so that
+            // `STARTED` is reached and reported.
+            self.wait_for_drain("start");
+        }
+
+        if self.state.load() == StreamState::Drained {
+            // Restarting after a drain, preroll as if the stream was new.
+            self.output_frame_count.store(0, Ordering::SeqCst);
+        }
+
+        if self
+            .cork(CorkState::uncork() | CorkState::notify())
+            .is_none()
+        {
+            // Already started.
+            return Ok(());
+        }
 
         if self.output_stream.is_some() {
+            self.context.mainloop.lock();
             /* When doing output-only or duplex, we need to manually call user cb once in order to
              * make things roll. This is done via a defer event in order to execute it from PA
              * server thread. */
-            self.context.mainloop.lock();
             self.context
                 .mainloop
                 .get_api()
@@ -646,17 +783,8 @@ impl StreamOps for PulseStream<'_> {
     }
 
     fn stop(&mut self) -> Result<()> {
-        {
-            self.context.mainloop.lock();
-            self.shutdown = true;
-            // If draining is taking place wait to finish
-            cubeb_log!("Stream stop: waiting for drain");
-            while !self.drain_timer.load(Ordering::Acquire).is_null() {
-                self.context.mainloop.wait();
-            }
-            cubeb_log!("Stream stop: waited for drain");
-            self.context.mainloop.unlock();
-        }
+        // If draining is taking place wait to finish
+        self.wait_for_drain("stop");
         self.cork(CorkState::cork() | CorkState::notify());
 
         Ok(())
@@ -669,19 +797,27 @@ impl StreamOps for PulseStream<'_> {
             self.context.mainloop.lock();
         }
 
-        if self.output_stream.is_none() {
-            cubeb_log!("Calling position() on an input-only stream");
-            return Err(Error::error());
-        }
+        // Duplex streams report the playback position, capture streams the
+        // position of the record stream's read index.
+        let timing = match (&self.output_stream, &self.input_stream) {
+            (Some(stm), _) => Some((stm, &self.output_sample_spec)),
+            (None, Some(stm)) => Some((stm, &self.input_sample_spec)),
+            (None, None) => None,
+        };
 
-        let stm = self.output_stream.as_ref().unwrap();
-        let r = match stm.get_time() {
-            Ok(r_usec) => {
-                let bytes = USecExt::to_bytes(r_usec, &self.output_sample_spec);
-                Ok((bytes / self.output_sample_spec.frame_size()) as u64)
-            }
-            Err(_) => {
-                cubeb_log!("Error: stm.get_time failed");
+        let r = match timing {
+            Some((stm, sample_spec)) => match stm.get_time() {
+                Ok(r_usec) => {
+                    let bytes = USecExt::to_bytes(r_usec, sample_spec);
+                    Ok((bytes / sample_spec.frame_size()) as u64)
+                }
+                Err(_) => {
+                    cubeb_log!("Error: stm.get_time failed");
+                    Err(Error::error())
+                }
+            },
+            None => {
+                cubeb_log!("Error: calling position() on a stream without input or output");
                 Err(Error::error())
             }
         };
@@ -761,8 +897,8 @@ impl StreamOps for PulseStream<'_> {
                         }
                     };
 
-                    if flags.contains(pulse::SinkFlags::FLAT_VOLUME) {
-                        self.volume = volume;
+                    if self.context.server.flat_volume(flags) {
+                        self.volume.store(volume.to_bits(), Ordering::Release);
                     } else {
                         let channels = stm.get_sample_spec().channels;
                         let vol = pulse::sw_volume_from_linear(f64::from(volume));
@@ -777,7 +913,11 @@ impl StreamOps for PulseStream<'_> {
                             context_success,
                             context_ptr,
                         ) {
-                            self.context.operation_wait(stm, &o);
+                            if !self.context.operation_wait(stm, &o) {
+                                self.context.mainloop.unlock();
+                                cubeb_log!("Error: set_volume: operation failed");
+                                return Err(Error::error());
+                            }
                         }
                     }
 
@@ -799,11 +939,17 @@ impl StreamOps for PulseStream<'_> {
             }
             Some(ref stm) => {
                 self.context.mainloop.lock();
-                if let Ok(o) = stm.set_name(name, stream_success, self as *const _ as *mut _) {
-                    self.context.operation_wait(stm, &o);
-                }
+                let r = match stm.set_name(name, stream_success, self as *const _ as *mut _) {
+                    Ok(o) => self.context.operation_wait(stm, &o),
+                    Err(_) => true,
+                };
                 self.context.mainloop.unlock();
-                Ok(())
+                if r {
+                    Ok(())
+                } else {
+                    cubeb_log!("Error: set_name: operation failed");
+                    Err(Error::error())
+                }
             }
         }
     }
@@ -873,6 +1019,7 @@ impl PulseStream<'_> {
         context: &pulse::Context,
         stream_params: &StreamParamsRef,
         stream_name: Option<&CStr>,
+        default_map: Option<&pulse::ChannelMap>,
     ) -> Result<pulse::Stream> {
         if stream_params.prefs() == StreamPrefs::LOOPBACK {
             cubeb_log!("Error: StreamPref::LOOPBACK unimplemented");
@@ -901,7 +1048,14 @@ impl PulseStream<'_> {
             rate: stream_params.rate(),
         };
 
+        let default_map =
+            default_map.filter(|map| u32::from(map.channels) == stream_params.channels());
+
         let cm: Option<pa_channel_map> = match stream_params.layout() {
+            ChannelLayout::UNDEFINED if default_map.is_some() => {
+                cubeb_log!("Layout undefined, using the default device's channel map.");
+                default_map.copied()
+            }
             ChannelLayout::UNDEFINED => {
                 if stream_params.channels() <= 8
                     && pulse::ChannelMap::init_auto(
@@ -945,21 +1099,45 @@ impl PulseStream<'_> {
         }
     }
 
-    fn cork(&mut self, state: CorkState) {
-        {
+    /// Corks or uncorks both streams. With `CorkState::notify()`, also moves
+    /// to `Stopped` or `Started` and reports it, returning the previous state
+    /// if the transition happened.
+    fn cork(&mut self, state: CorkState) -> Option<StreamState> {
+        let next = if state.is_cork() {
+            StreamState::Stopped
+        } else {
+            StreamState::Started
+        };
+
+        let previous = {
             self.context.mainloop.lock();
             self.cork_stream(self.output_stream.as_ref(), state);
             self.cork_stream(self.input_stream.as_ref(), state);
-            self.context.mainloop.unlock()
+            let previous = if state.is_notify() {
+                self.set_state(next)
+            } else {
+                None
+            };
+            self.context.mainloop.unlock();
+            previous
+        };
+
+        if previous.is_some() {
+            self.notify_state(next);
         }
+        previous
+    }
 
-        if state.is_notify() {
-            self.state_change_callback(if state.is_cork() {
-                ffi::CUBEB_STATE_STOPPED
-            } else {
-                ffi::CUBEB_STATE_STARTED
-            });
+    /// Waits for a drain in progress, if any, to complete and move to
+    /// `Drained`.
+    fn wait_for_drain(&self, caller: &str) {
+        self.context.mainloop.lock();
+        cubeb_log!("Stream {}: waiting for drain", caller);
+        while !self.drain_timer.load(Ordering::Acquire).is_null() {
+            self.context.mainloop.wait();
         }
+        cubeb_log!("Stream {}: waited for drain", caller);
+        self.context.mainloop.unlock();
     }
 
     fn update_timing_info(&self) -> bool {
@@ -984,26 +1162,55 @@ impl PulseStream<'_> {
         r
     }
 
-    pub fn state_change_callback(&mut self, s: ffi::cubeb_state) {
-        self.state = s;
-        unsafe {
-            (self.state_callback.unwrap())(
-                self as *mut PulseStream as *mut ffi::cubeb_stream,
-                self.user_ptr,
-                s,
-            )
+    /// Moves to `next` if the state machine allows it, returning the previous
+    /// state.
+    fn set_state(&self, next: StreamState) -> Option<StreamState> {
+        let previous = match self.state.transition(next) {
+            Some(previous) => previous,
+            None => {
+                cubeb_logv!("Ignoring stream state change to {:?}", next);
+                return None;
+            }
         };
+
+        if previous == StreamState::Draining && next != StreamState::Drained {
+            // Leaving a drain early, its timer must not fire.
+            let drain_timer = self.drain_timer.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !drain_timer.is_null() {
+                /* there's no pa_rttime_free, so use this instead. */
+                self.context.mainloop.get_api().time_free(drain_timer);
+                self.context.mainloop.signal();
+            }
+        }
+
+        Some(previous)
+    }
+
+    fn notify_state(&mut self, s: StreamState) {
+        if let Some(s) = s.to_cubeb_state() {
+            unsafe {
+                (self.state_callback.unwrap())(
+                    self as *mut PulseStream as *mut ffi::cubeb_stream,
+                    self.user_ptr,
+                    s,
+                )
+            };
+        }
+    }
+
+    pub fn transition(&mut self, next: StreamState) {
+        if self.set_state(next).is_some() {
+            self.notify_state(next);
+        }
     }
 
     fn wait_until_ready(&self) -> bool {
-        fn wait_until_io_stream_ready(
-            stm: &pulse::Stream,
-            mainloop: &pulse::ThreadedMainloop,
-        ) -> bool {
-            if mainloop.is_null() {
+        fn wait_until_io_stream_ready(stm: &pulse::Stream, context: &PulseContext) -> bool {
+            if context.mainloop.is_null() {
                 return false;
             }
 
+            let deadline = context.operation_deadline();
             loop {
                 let state = stm.get_state();
                 if !state.is_good() {
@@ -1012,20 +1219,24 @@ impl PulseStream<'_> {
                 if state == pulse::StreamState::Ready {
                     break;
                 }
-                mainloop.wait();
+                if deadline.expired() {
+                    cubeb_log!("Error: timed out waiting for the stream to be ready");
+                    return false;
+                }
+                context.mainloop.wait();
             }
 
             true
         }
 
         if let Some(ref stm) = self.output_stream {
-            if !wait_until_io_stream_ready(stm, &self.context.mainloop) {
+            if !wait_until_io_stream_ready(stm, self.context) {
                 return false;
             }
         }
 
         if let Some(ref stm) = self.input_stream {
-            if !wait_until_io_stream_ready(stm, &self.context.mainloop) {
+            if !wait_until_io_stream_ready(stm, self.context) {
                 return false;
             }
         }
@@ -1045,10 +1256,10 @@ impl PulseStream<'_> {
             let stm = unsafe { &mut *(u as *mut PulseStream) };
             let drain_timer = stm.drain_timer.load(Ordering::Acquire);
             debug_assert_eq!(drain_timer, e);
-            stm.state_change_callback(ffi::CUBEB_STATE_DRAINED);
             /* there's no pa_rttime_free, so use this instead. */
             a.time_free(drain_timer);
             stm.drain_timer.store(ptr::null_mut(), Ordering::Release);
+            stm.transition(StreamState::Drained);
             stm.context.mainloop.signal();
         }
 
@@ -1086,14 +1297,7 @@ impl PulseStream<'_> {
                         };
                         if got < 0 {
                             let _ = stm.cancel_write();
-                            self.shutdown = true;
-                            unsafe {
-                                self.state_callback.unwrap()(
-                                    self as *const _ as *mut _,
-                                    self.user_ptr,
-                                    ffi::CUBEB_STATE_ERROR,
-                                );
-                            }
+                            self.transition(StreamState::Error);
                             return;
                         }
 
@@ -1103,7 +1307,8 @@ impl PulseStream<'_> {
                             read_offset += (size / frame_size) * in_frame_size;
                         }
 
-                        if self.volume != PULSE_NO_GAIN {
+                        let volume = f32::from_bits(self.volume.load(Ordering::Acquire));
+                        if volume != PULSE_NO_GAIN {
                             let samples = (self.output_sample_spec.channels as usize * size
                                 / frame_size) as isize;
 
@@ -1112,12 +1317,12 @@ impl PulseStream<'_> {
                             {
                                 let b = buffer as *mut i16;
                                 for i in 0..samples {
-                                    unsafe { *b.offset(i) *= self.volume as i16 };
+                                    unsafe { *b.offset(i) *= volume as i16 };
                                 }
                             } else {
                                 let b = buffer as *mut f32;
                                 for i in 0..samples {
-                                    unsafe { *b.offset(i) *= self.volume };
+                                    unsafe { *b.offset(i) *= volume };
                                 }
                             }
                         }
@@ -1175,20 +1380,20 @@ impl PulseStream<'_> {
                             };
 
                             /* pa_stream_drain is useless, see PA bug# 866. this is a workaround. */
-                            /* arbitrary safety margin: double the current latency. */
                             debug_assert!(self.drain_timer.load(Ordering::Acquire).is_null());
                             let stream_ptr = self as *const _ as *mut _;
                             if let Some(ref context) = self.context.context {
                                 self.drain_timer.store(
                                     context.rttime_new(
-                                        pulse::rtclock_now() + 2 * latency,
+                                        pulse::rtclock_now()
+                                            + self.context.server.drain_delay(latency),
                                         drained_cb,
                                         stream_ptr,
                                     ),
                                     Ordering::Release,
                                 );
                             }
-                            self.shutdown = true;
+                            self.set_state(StreamState::Draining);
                             return;
                         }
 
@@ -1229,9 +1434,56 @@ fn not_supported() -> Error {
 
 #[cfg(all(test, not(feature = "pulse-dlopen")))]
 mod test {
-    use super::layout_to_channel_map;
+    use super::{layout_to_channel_map, InputRing};
     use cubeb_backend::ChannelLayout;
     use pulse_ffi::*;
+    use std::os::raw::c_void;
+    use std::slice;
+
+    fn push(ring: &mut InputRing<f32>, samples: &[f32]) {
+        ring.push(samples.as_ptr() as *const c_void, samples.len());
+    }
+
+    fn acquire(ring: &mut InputRing<f32>, nsamples: usize) -> (*const c_void, Vec<f32>) {
+        let p = ring.acquire(nsamples);
+        let data = unsafe { slice::from_raw_parts(p as *const f32, nsamples) }.to_vec();
+        (p, data)
+    }
+
+    #[test]
+    fn input_ring_lends_contiguous_samples() {
+        let mut ring = InputRing::<f32>::new(8);
+        push(&mut ring, &[1., 2., 3., 4.]);
+        let (p, data) = acquire(&mut ring, 3);
+        assert_eq!(data, [1., 2., 3.]);
+        assert_ne!(p, ring.linear_input_buffer.as_ptr() as *const c_void);
+        assert_eq!(ring.consumer.len(), 4);
+        ring.release();
+        assert_eq!(ring.consumer.len(), 1);
+        assert_eq!(acquire(&mut ring, 1).1, [4.]);
+    }
+
+    #[test]
+    fn input_ring_copies_wrapped_samples() {
+        let mut ring = InputRing::<f32>::new(4);
+        push(&mut ring, &[1., 2., 3.]);
+        ring.trim(0);
+        push(&mut ring, &[4., 5., 6., 7.]);
+        let (p, data) = acquire(&mut ring, 4);
+        assert_eq!(data, [4., 5., 6., 7.]);
+        assert_eq!(p, ring.linear_input_buffer.as_ptr() as *const c_void);
+        ring.release();
+        assert_eq!(ring.consumer.len(), 0);
+    }
+
+    #[test]
+    fn input_ring_pads_underrun_with_silence() {
+        let mut ring = InputRing::<f32>::new(8);
+        push(&mut ring, &[1., 2.]);
+        assert_eq!(acquire(&mut ring, 4).1, [1., 2., 0., 0.]);
+        ring.release();
+        assert_eq!(ring.consumer.len(), 0);
+    }
 
     macro_rules! channel_tests {
         {$($name: ident, $layout: ident => [ $($channels: ident),* ]),+} => {
@@ -0,0 +1,136 @@
+// Copyright © 2017-2018 Mozilla Foundation
+//
+// This program is made available under an ISC-style license.  See the
+// accompanying file LICENSE for details.
+
+use cubeb_backend::ffi;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Lifecycle of a `PulseStream`.
+///
+/// Data callbacks are only delivered in `Started`. `Draining` is internal:
+/// the last buffer has been written and the stream is waiting for it to be
+/// played out before reporting `Drained`. `Error` is terminal.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamState {
+    Stopped,
+    Started,
+    Draining,
+    Drained,
+    Error,
+}
+
+impl StreamState {
+    pub fn can_transition_to(self, next: StreamState) -> bool {
+        use self::StreamState::*;
+        matches!(
+            (self, next),
+            (Stopped, Started)
+                | (Drained, Started)
+                | (Started, Stopped)
+                | (Draining, Stopped)
+                | (Drained, Stopped)
+                | (Started, Draining)
+                | (Started, Drained)
+                | (Draining, Drained)
+                | (Stopped, Error)
+                | (Started, Error)
+                | (Draining, Error)
+                | (Drained, Error)
+        )
+    }
+
+    /// The state reported to the user's state callback, if any.
+    pub fn to_cubeb_state(self) -> Option<ffi::cubeb_state> {
+        match self {
+            StreamState::Stopped => Some(ffi::CUBEB_STATE_STOPPED),
+            StreamState::Started => Some(ffi::CUBEB_STATE_STARTED),
+            StreamState::Draining => None,
+            StreamState::Drained => Some(ffi::CUBEB_STATE_DRAINED),
+            StreamState::Error => Some(ffi::CUBEB_STATE_ERROR),
+        }
+    }
+
+    fn from_u8(x: u8) -> Self {
+        match x {
+            0 => StreamState::Stopped,
+            1 => StreamState::Started,
+            2 => StreamState::Draining,
+            3 => StreamState::Drained,
+            _ => StreamState::Error,
+        }
+    }
+}
+
+/// `StreamState` shared between the mainloop thread and API threads.
+#[derive(Debug)]
+pub struct AtomicStreamState(AtomicU8);
+
+impl AtomicStreamState {
+    pub fn new(state: StreamState) -> Self {
+        AtomicStreamState(AtomicU8::new(state as u8))
+    }
+
+    pub fn load(&self) -> StreamState {
+        StreamState::from_u8(self.0.load(Ordering::Acquire))
+    }
+
+    /// Atomically moves to `next` if the state machine allows it, returning
+    /// the previous state on success.
+    pub fn transition(&self, next: StreamState) -> Option<StreamState> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |s| {
+                if StreamState::from_u8(s).can_transition_to(next) {
+                    Some(next as u8)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(StreamState::from_u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AtomicStreamState;
+    use super::StreamState::*;
+
+    #[test]
+    fn restart() {
+        assert!(Stopped.can_transition_to(Started));
+        assert!(Started.can_transition_to(Draining));
+        assert!(Draining.can_transition_to(Drained));
+        assert!(Drained.can_transition_to(Started));
+        assert!(Drained.can_transition_to(Stopped));
+        assert!(Draining.can_transition_to(Stopped));
+    }
+
+    #[test]
+    fn redundant_transitions() {
+        assert!(!Stopped.can_transition_to(Stopped));
+        assert!(!Started.can_transition_to(Started));
+        assert!(!Draining.can_transition_to(Started));
+        assert!(!Stopped.can_transition_to(Drained));
+    }
+
+    #[test]
+    fn error_is_terminal() {
+        for s in [Stopped, Started, Draining, Drained, Error] {
+            assert!(!Error.can_transition_to(s));
+        }
+        assert!(Started.can_transition_to(Error));
+    }
+
+    #[test]
+    fn atomic_transition() {
+        let state = AtomicStreamState::new(Stopped);
+        assert_eq!(state.transition(Started), Some(Stopped));
+        assert_eq!(state.transition(Started), None);
+        assert_eq!(state.load(), Started);
+        assert_eq!(state.transition(Error), Some(Started));
+        assert_eq!(state.transition(Stopped), None);
+        assert_eq!(state.load(), Error);
+    }
+}
@@ -3,9 +3,14 @@
 // This program is made available under an ISC-style license.  See the
 // accompanying file LICENSE for details.
 
-use backend::PulseContext;
+use backend::{ConnectOptions, DefaultDeviceChangedCallback, PulseContext};
 use cubeb_backend::{capi, ffi};
-use std::os::raw::{c_char, c_int};
+use pulse;
+use pulse_ffi;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::time::Duration;
 
 /// # Safety
 ///
@@ -19,3 +24,208 @@ pub unsafe extern "C" fn pulse_rust_init(
 ) -> c_int {
     capi::capi_init::<PulseContext>(c, context_name)
 }
+
+/// Don't autospawn a server if none is running.
+pub const PULSE_RUST_CONNECT_NOAUTOSPAWN: c_uint = pulse_ffi::PA_CONTEXT_NOAUTOSPAWN;
+/// Don't fail if no server is available, wait for one to appear instead.
+pub const PULSE_RUST_CONNECT_NOFAIL: c_uint = pulse_ffi::PA_CONTEXT_NOFAIL;
+/// Share one mainloop thread and server connection with the other contexts
+/// initialized with this flag and the same server and flags.
+pub const PULSE_RUST_CONNECT_SHARED: c_uint = 1 << 31;
+/// Connect on the first call that needs the server rather than in
+/// `pulse_rust_init_with_options`.
+pub const PULSE_RUST_CONNECT_LAZY: c_uint = 1 << 30;
+
+/// Options for `pulse_rust_init_with_options`.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug)]
+pub struct pulse_rust_connect_options {
+    /// Server to connect to, e.g. `unix:/run/pulse/native` or
+    /// `tcp:localhost:4713`. NULL uses the default server, honoring
+    /// `PULSE_SERVER`.
+    pub server: *const c_char,
+    /// `PULSE_RUST_CONNECT_*` flags.
+    pub flags: c_uint,
+    /// Milliseconds to wait for the connection to the server, 0 to wait
+    /// forever.
+    pub connect_timeout_ms: c_uint,
+    /// Milliseconds to wait for each operation on the server before giving
+    /// up on it, 0 to wait forever.
+    pub operation_timeout_ms: c_uint,
+    /// Identity of the application shown by mixers, each NULL if unset:
+    /// a reverse domain name such as `org.example.App`, an icon name from
+    /// the XDG icon theme and a version string.
+    pub application_id: *const c_char,
+    pub application_icon_name: *const c_char,
+    pub application_version: *const c_char,
+}
+
+unsafe fn to_owned_cstr(s: *const c_char) -> Option<CString> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_owned())
+    }
+}
+
+fn timeout_from_ms(ms: c_uint) -> Option<Duration> {
+    match ms {
+        0 => None,
+        ms => Some(Duration::from_millis(u64::from(ms))),
+    }
+}
+
+/// Like `pulse_rust_init`, connecting to the server as described by
+/// `options`, which may be NULL for the defaults.
+///
+/// # Safety
+///
+/// Entry point from C code. This function is unsafe because it dereferences
+/// the given `c`, `context_name` and `options` pointers, and the `server`
+/// string of `options`. The caller should ensure those pointers are valid.
+#[no_mangle]
+pub unsafe extern "C" fn pulse_rust_init_with_options(
+    c: *mut *mut ffi::cubeb,
+    context_name: *const c_char,
+    options: *const pulse_rust_connect_options,
+) -> c_int {
+    if c.is_null() {
+        return ffi::CUBEB_ERROR_INVALID_PARAMETER;
+    }
+
+    let mut connect_options = ConnectOptions::default();
+    if let Some(options) = options.as_ref() {
+        connect_options.shared = options.flags & PULSE_RUST_CONNECT_SHARED != 0;
+        connect_options.lazy = options.flags & PULSE_RUST_CONNECT_LAZY != 0;
+        let flags = options.flags & !(PULSE_RUST_CONNECT_SHARED | PULSE_RUST_CONNECT_LAZY);
+        connect_options.flags = match pulse::ContextFlags::from_bits(flags) {
+            Some(flags) => flags,
+            None => return ffi::CUBEB_ERROR_INVALID_PARAMETER,
+        };
+        connect_options.server = to_owned_cstr(options.server);
+        connect_options.connect_timeout = timeout_from_ms(options.connect_timeout_ms);
+        connect_options.operation_timeout = timeout_from_ms(options.operation_timeout_ms);
+        connect_options.application_id = to_owned_cstr(options.application_id);
+        connect_options.application_icon_name = to_owned_cstr(options.application_icon_name);
+        connect_options.application_version = to_owned_cstr(options.application_version);
+    }
+
+    let context_name = if context_name.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(context_name))
+    };
+    match PulseContext::init_with_options(context_name, connect_options) {
+        Ok(context) => {
+            *c = context.as_ptr();
+            // Leaking pointer across C FFI
+            mem::forget(context);
+            ffi::CUBEB_OK
+        }
+        Err(e) => e.raw_code(),
+    }
+}
+
+/// Hide (`hide != 0`) or show the monitor sources of sinks in the input
+/// devices returned by `cubeb_enumerate_devices`. They are shown by default.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences the given `c` pointer,
+/// which must be a context created by `pulse_rust_init`.
+#[no_mangle]
+pub unsafe extern "C" fn pulse_rust_set_hide_monitor_sources(
+    c: *mut ffi::cubeb,
+    hide: c_int,
+) -> c_int {
+    if c.is_null() {
+        return ffi::CUBEB_ERROR_INVALID_PARAMETER;
+    }
+    let ctx = &mut *(c as *mut PulseContext);
+    ctx.set_hide_monitor_sources(hide != 0);
+    ffi::CUBEB_OK
+}
+
+/// Get the sample rate and channel count of the default input device.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences the given `c`, `rate`
+/// and `channels` pointers. `c` must be a context created by
+/// `pulse_rust_init`, and `rate` and `channels` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pulse_rust_get_default_input_params(
+    c: *mut ffi::cubeb,
+    rate: *mut u32,
+    channels: *mut u32,
+) -> c_int {
+    if c.is_null() || rate.is_null() || channels.is_null() {
+        return ffi::CUBEB_ERROR_INVALID_PARAMETER;
+    }
+    let ctx = &mut *(c as *mut PulseContext);
+    match ctx.default_input_params() {
+        Ok((r, ch)) => {
+            *rate = r;
+            *channels = ch;
+            ffi::CUBEB_OK
+        }
+        Err(e) => e.raw_code(),
+    }
+}
+
+/// Enable (`enable != 0`) or disable switching a Bluetooth card to a profile
+/// with an input, such as a headset's head unit profile, when an input
+/// stream is opened on it. When the input device (or the default one) isn't
+/// a source, as with a headset in its A2DP profile, the card is the one
+/// behind the stream's output device, or the default output device. The
+/// previous profile is restored once the last such stream is destroyed.
+/// Disabled by default.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences the given `c` pointer,
+/// which must be a context created by `pulse_rust_init`.
+#[no_mangle]
+pub unsafe extern "C" fn pulse_rust_set_switch_bluetooth_profiles(
+    c: *mut ffi::cubeb,
+    enable: c_int,
+) -> c_int {
+    if c.is_null() {
+        return ffi::CUBEB_ERROR_INVALID_PARAMETER;
+    }
+    let ctx = &mut *(c as *mut PulseContext);
+    ctx.switch_bluetooth_profiles = enable != 0;
+    ffi::CUBEB_OK
+}
+
+/// Register `callback`, or unregister with NULL, to be told when the default
+/// input or output device changes. It gets the previous and new default
+/// device IDs, as reported by `cubeb_enumerate_devices`. The previous one is
+/// only valid during the call.
+///
+/// The callback is called on the PulseAudio mainloop thread and must not
+/// call back into the context.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences the given `c` pointer,
+/// which must be a context created by `pulse_rust_init`.
+#[no_mangle]
+pub unsafe extern "C" fn pulse_rust_register_default_device_changed(
+    c: *mut ffi::cubeb,
+    callback: Option<DefaultDeviceChangedCallback>,
+    user_ptr: *mut c_void,
+) -> c_int {
+    if c.is_null() {
+        return ffi::CUBEB_ERROR_INVALID_PARAMETER;
+    }
+    let ctx = &mut *(c as *mut PulseContext);
+    if callback.is_some() && ctx.ensure_connected().is_err() {
+        return ffi::CUBEB_ERROR;
+    }
+    match ctx.set_default_device_changed_callback(callback, user_ptr) {
+        Ok(()) => ffi::CUBEB_OK,
+        Err(_) => ffi::CUBEB_ERROR,
+    }
+}
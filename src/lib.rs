@@ -15,4 +15,10 @@ extern crate semver;
 mod backend;
 mod capi;
 
-pub use capi::pulse_rust_init;
+pub use capi::{
+    pulse_rust_connect_options, pulse_rust_get_default_input_params, pulse_rust_init,
+    pulse_rust_init_with_options, pulse_rust_register_default_device_changed,
+    pulse_rust_set_hide_monitor_sources, pulse_rust_set_switch_bluetooth_profiles,
+    PULSE_RUST_CONNECT_LAZY, PULSE_RUST_CONNECT_NOAUTOSPAWN, PULSE_RUST_CONNECT_NOFAIL,
+    PULSE_RUST_CONNECT_SHARED,
+};